@@ -0,0 +1,142 @@
+//! Atomic state controlled from other threads.
+//!
+//! [`set_state`][crate::ScopeGuard::set_state] needs exclusive access to the guard,
+//! so the decision cannot be made by another thread. [`StateController`] is a
+//! cloneable handle to an atomic state, which can be sent to other threads, and
+//! decides the state a linked guard will see when it drops.
+//!
+//! The state is stored as a `u8`, so its type shall implement [`AtomicRepr`],
+//! which is usually a `#[repr(u8)]` enum.
+//!
+//! ```
+//! use std::sync::mpsc;
+//! use std::thread;
+//! use stated_scope_guard::atomic::{AtomicRepr, StateController};
+//!
+//! #[derive(Debug, Clone, Copy, PartialEq)]
+//! #[repr(u8)]
+//! enum State {
+//!     Aborted,
+//!     Committed,
+//! }
+//!
+//! impl AtomicRepr for State {
+//!     fn into_repr(self) -> u8 {
+//!         self as u8
+//!     }
+//!
+//!     fn from_repr(repr: u8) -> Self {
+//!         match repr {
+//!             0 => State::Aborted,
+//!             _ => State::Committed,
+//!         }
+//!     }
+//! }
+//!
+//! let controller = StateController::new(State::Aborted);
+//! let (done, wait_done) = mpsc::channel();
+//! let worker = {
+//!     let controller = controller.clone();
+//!     thread::spawn(move || {
+//!         let _guard = controller.guard("resource", |_, state| {
+//!             assert_eq!(*state, State::Committed);
+//!         });
+//!         wait_done.recv().unwrap();
+//!     })
+//! };
+//! // The coordinator decides to commit
+//! controller.set(State::Committed);
+//! done.send(()).unwrap();
+//! worker.join().unwrap();
+//! ```
+
+use alloc::sync::Arc;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::shared::{LinkedScopeGuard, StateHandle};
+
+/// State which can be converted to and from a `u8`
+pub trait AtomicRepr: Copy {
+    /// Convert the state to its `u8` representation.
+    fn into_repr(self) -> u8;
+
+    /// Convert a `u8` representation produced by
+    /// [`into_repr`][AtomicRepr::into_repr] back to the state.
+    fn from_repr(repr: u8) -> Self;
+}
+
+impl AtomicRepr for u8 {
+    fn into_repr(self) -> u8 {
+        self
+    }
+
+    fn from_repr(repr: u8) -> Self {
+        repr
+    }
+}
+
+impl AtomicRepr for bool {
+    fn into_repr(self) -> u8 {
+        self as u8
+    }
+
+    fn from_repr(repr: u8) -> Self {
+        repr != 0
+    }
+}
+
+/// Cloneable handle to an atomic state, which can be sent to other threads
+pub struct StateController<S> {
+    state: Arc<AtomicU8>,
+    /// The state is stored as its `u8` representation
+    _state: PhantomData<fn() -> S>,
+}
+
+impl<S> Clone for StateController<S> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            _state: PhantomData,
+        }
+    }
+}
+
+impl<S: AtomicRepr> StateController<S> {
+    /// Create a new atomic state with initial `state`.
+    pub fn new(state: S) -> Self {
+        Self {
+            state: Arc::new(AtomicU8::new(state.into_repr())),
+            _state: PhantomData,
+        }
+    }
+
+    /// Set the atomic state to `state`
+    pub fn set(&self, state: S) {
+        self.state.store(state.into_repr(), Ordering::Release);
+    }
+
+    /// Current atomic state
+    pub fn get(&self) -> S {
+        S::from_repr(self.state.load(Ordering::Acquire))
+    }
+
+    /// Create a new stated scope guard linked to this atomic state.
+    ///
+    /// The `callback` takes `value` and the atomic state at the time of
+    /// dropping as parameter.
+    pub fn guard<T, F, R>(&self, value: T, callback: F) -> LinkedScopeGuard<T, Self, F>
+    where
+        F: FnOnce(T, &S) -> R,
+    {
+        LinkedScopeGuard::linked(value, self.clone(), callback)
+    }
+}
+
+impl<S: AtomicRepr> StateHandle for StateController<S> {
+    type State = S;
+
+    fn snapshot(&self) -> S {
+        self.get()
+    }
+}
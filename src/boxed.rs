@@ -0,0 +1,158 @@
+//! Type-erased stated scope guard.
+//!
+//! Each [`ScopeGuard`] with a closure callback has a unique type, so guards for
+//! different resources cannot be stored in the same collection. [`DynScopeGuard`]
+//! erases the type of value and callback, and only keeps the type of state, so
+//! that guards registered at runtime, such as by plugins, can be stored in a `Vec`.
+//! [`SendDynScopeGuard`] is the same, except that it can be sent to other threads.
+//!
+//! ```
+//! use std::cell::RefCell;
+//! use stated_scope_guard::ScopeGuard;
+//! use stated_scope_guard::boxed::DynScopeGuard;
+//!
+//! let rolled_back = RefCell::new(Vec::new());
+//! {
+//!     let mut guards: Vec<DynScopeGuard<'_, bool>> = Vec::new();
+//!     guards.push(DynScopeGuard::new("log dir", false, |name, committed| {
+//!         if !*committed { rolled_back.borrow_mut().push(name) }
+//!     }));
+//!     guards.push(
+//!         ScopeGuard::new(42, false, |_, committed| {
+//!             if !*committed { rolled_back.borrow_mut().push("answer") }
+//!         })
+//!         .into(),
+//!     );
+//!     guards[0].set_state(true);
+//! }
+//! assert_eq!(rolled_back.into_inner(), ["answer"]);
+//! ```
+//!
+//! ```
+//! use stated_scope_guard::boxed::SendDynScopeGuard;
+//!
+//! let guard = SendDynScopeGuard::new_send(String::from("log dir"), false, |name, committed| {
+//!     assert!(!*committed);
+//!     assert_eq!(name, "log dir");
+//! });
+//! std::thread::spawn(move || drop(guard)).join().unwrap();
+//! ```
+
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+
+use crate::{ScopeGuard, StatedDrop};
+
+/// Callback of the inner [`ScopeGuard`] of [`DynScopeGuard`], which calls the
+/// boxed callback
+struct CallBoxed;
+
+impl<S, C> StatedDrop<Box<C>, S> for CallBoxed
+where
+    C: FnOnce(&S) + ?Sized,
+{
+    type Output = ();
+
+    fn call(self, callback: Box<C>, state: &S) {
+        callback(state)
+    }
+}
+
+/// Type-erased stated scope guard
+///
+/// `C` is the type of the boxed callback, which has already captured the value.
+pub struct DynScopeGuard<'a, S, C = dyn FnOnce(&S) + 'a>
+where
+    C: FnOnce(&S) + ?Sized,
+{
+    guard: ScopeGuard<Box<C>, S, CallBoxed>,
+    /// The callback may borrow data of lifetime `'a`
+    _lifetime: PhantomData<&'a ()>,
+}
+
+/// Type-erased stated scope guard which can be sent to other threads
+pub type SendDynScopeGuard<'a, S> = DynScopeGuard<'a, S, dyn FnOnce(&S) + Send + 'a>;
+
+impl<'a, S> DynScopeGuard<'a, S> {
+    /// Create a new type-erased stated scope guard.
+    pub fn new<T, F>(value: T, state: S, callback: F) -> Self
+    where
+        T: 'a,
+        F: FnOnce(T, &S) + 'a,
+    {
+        Self::from_boxed(Box::new(move |state: &S| callback(value, state)), state)
+    }
+}
+
+impl<'a, S> SendDynScopeGuard<'a, S> {
+    /// Create a new type-erased stated scope guard which can be sent to other threads.
+    ///
+    /// It is not named `new` to avoid ambiguity with [`DynScopeGuard::new`].
+    pub fn new_send<T, F>(value: T, state: S, callback: F) -> Self
+    where
+        T: Send + 'a,
+        F: FnOnce(T, &S) + Send + 'a,
+    {
+        Self::from_boxed(Box::new(move |state: &S| callback(value, state)), state)
+    }
+}
+
+impl<S, C> DynScopeGuard<'_, S, C>
+where
+    C: FnOnce(&S) + ?Sized,
+{
+    /// Create a new type-erased stated scope guard from a boxed callback.
+    fn from_boxed(callback: Box<C>, state: S) -> Self {
+        Self {
+            guard: ScopeGuard::with_handler(callback, state, CallBoxed),
+            _lifetime: PhantomData,
+        }
+    }
+
+    /// Set current state to `state`
+    pub fn set_state(&mut self, state: S) {
+        self.guard.set_state(state);
+    }
+
+    /// Finish the scope guard now, calling the callback with current state.
+    pub fn finish(self) {
+        self.guard.finish()
+    }
+
+    /// Defuse the scope guard, and take the state out without calling the callback.
+    pub fn into_state(self) -> S {
+        self.guard.into_parts().1
+    }
+}
+
+impl<'a, T, S, F> From<ScopeGuard<T, S, F>> for DynScopeGuard<'a, S>
+where
+    T: 'a,
+    F: StatedDrop<T, S> + 'a,
+{
+    fn from(guard: ScopeGuard<T, S, F>) -> Self {
+        let (value, state, callback) = guard.into_parts();
+        Self::from_boxed(
+            Box::new(move |state: &S| {
+                callback.call(value, state);
+            }),
+            state,
+        )
+    }
+}
+
+impl<'a, T, S, F> From<ScopeGuard<T, S, F>> for SendDynScopeGuard<'a, S>
+where
+    T: Send + 'a,
+    F: StatedDrop<T, S> + Send + 'a,
+{
+    fn from(guard: ScopeGuard<T, S, F>) -> Self {
+        let (value, state, callback) = guard.into_parts();
+        Self::from_boxed(
+            Box::new(move |state: &S| {
+                callback.call(value, state);
+            }),
+            state,
+        )
+    }
+}
@@ -3,29 +3,74 @@
 //! For a more common and simple situation, where there are only two states,
 //! and the default state action is to revert, the other is do nothing, we can
 //! use [`DismissibleScopeGuard`].
+//!
+//! Like any [`ScopeGuard`], the value can be taken out without calling the callback
+//! by [`into_inner`][ScopeGuard::into_inner], which is useful to build a resource
+//! under a guard, and return it to the caller on success:
+//!
+//! ```
+//! use stated_scope_guard::dismissible::new_dismissible;
+//!
+//! # struct LogDir;
+//! # fn delete_log_dir(_: LogDir) {}
+//! # fn prepare(_: &mut LogDir) -> Result<(), ()> { Ok(()) }
+//! fn create_log_dir() -> Result<LogDir, ()> {
+//!     let mut log_dir = new_dismissible(LogDir, delete_log_dir);
+//!     prepare(&mut log_dir)?;
+//!     Ok(log_dir.into_inner())
+//! }
+//! # create_log_dir().unwrap();
+//! ```
 
-use crate::ScopeGuard;
+use crate::{ScopeGuard, StatedDrop};
 
 /// Dismissible stated scope guard
 pub type DismissibleScopeGuard<T, F> = ScopeGuard<T, bool, F>;
 
+/// Callback of a dismissible stated scope guard created by [`new_dismissible`],
+/// which calls the inner callback only if the guard is not dismissed
+///
+/// With a function pointer as inner callback, the type of the guard is fully
+/// nameable, such as `DismissibleScopeGuard<File, DismissibleCallback<fn(File)>>`.
+pub struct DismissibleCallback<F>(F);
+
+impl<T, F> StatedDrop<T, bool> for DismissibleCallback<F>
+where
+    F: FnOnce(T),
+{
+    type Output = ();
+
+    fn call(self, value: T, state: &bool) {
+        if *state {
+            (self.0)(value)
+        }
+    }
+}
+
 /// Create a new dismissible stated scope guard, the `callback` will always
 /// be called when dropped unless [`dismiss`][DismissibleScopeGuard::dismiss] is called.
 /// As a result, we don't need to check the state in the passed `callback`.
 pub fn new_dismissible<T, F: FnOnce(T)>(
     value: T,
     callback: F,
-) -> DismissibleScopeGuard<T, impl FnOnce(T, &bool)> {
-    ScopeGuard::new(value, true, |value, state| {
-        if *state {
-            callback(value)
-        }
-    })
+) -> DismissibleScopeGuard<T, DismissibleCallback<F>> {
+    DismissibleScopeGuard::new_dismissible(value, callback)
+}
+
+impl<T, F> DismissibleScopeGuard<T, DismissibleCallback<F>>
+where
+    F: FnOnce(T),
+{
+    /// Create a new dismissible stated scope guard. This is the same as
+    /// [`new_dismissible`], but can be called on a named guard type.
+    pub fn new_dismissible(value: T, callback: F) -> Self {
+        ScopeGuard::with_handler(value, true, DismissibleCallback(callback))
+    }
 }
 
 impl<T, F> DismissibleScopeGuard<T, F>
 where
-    F: FnOnce(T, &bool),
+    F: StatedDrop<T, bool>,
 {
     /// Dismiss the scope guard callback. After this function, the callback
     /// will not be called when [`DismissibleScopeGuard`] is dropped.
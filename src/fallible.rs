@@ -0,0 +1,118 @@
+//! Fallible stated scope guard.
+//!
+//! Real cleanups may fail, such as deleting a directory or closing a socket.
+//! [`FallibleScopeGuard`] takes a callback returning `Result<(), E>`, and an
+//! error sink. When the guard is dropped, the error returned by the callback
+//! is passed to the sink, which may log it, store it, or forward it to a global
+//! hook. When the guard is [finished][FallibleScopeGuard::finish] explicitly,
+//! the error is returned directly instead.
+//!
+//! ```
+//! use std::cell::RefCell;
+//! use stated_scope_guard::fallible::FallibleScopeGuard;
+//!
+//! fn remove(path: &str) -> Result<(), String> {
+//!     Err(format!("cannot remove {path}"))
+//! }
+//!
+//! let errors = RefCell::new(Vec::new());
+//! {
+//!     let _guard = FallibleScopeGuard::new(
+//!         "/tmp/log",
+//!         false,
+//!         |path, committed| if *committed { Ok(()) } else { remove(path) },
+//!         |error| errors.borrow_mut().push(error),
+//!     );
+//! }
+//! assert_eq!(errors.into_inner(), ["cannot remove /tmp/log"]);
+//!
+//! let guard = FallibleScopeGuard::new(
+//!     "/tmp/log",
+//!     false,
+//!     |path, committed| if *committed { Ok(()) } else { remove(path) },
+//!     |_| unreachable!(),
+//! );
+//! assert!(guard.finish().is_err());
+//! ```
+
+use core::ops::{Deref, DerefMut};
+
+use crate::ScopeGuard;
+
+/// Fallible stated scope guard
+///
+/// The value, callback and error sink are stored together as the value of an
+/// inner [`ScopeGuard`], whose callback is a function pointer, so that the
+/// callback and sink can be taken out again by
+/// [`finish`][FallibleScopeGuard::finish].
+pub struct FallibleScopeGuard<T, S, F, K> {
+    guard: Inner<T, S, F, K>,
+}
+
+/// Inner [`ScopeGuard`] of [`FallibleScopeGuard`]
+type Inner<T, S, F, K> = ScopeGuard<(T, F, K), S, fn((T, F, K), &S)>;
+
+/// Call `callback`, and pass its error to `sink`, if any.
+fn report<T, S, E, F, K>((value, callback, sink): (T, F, K), state: &S)
+where
+    F: FnOnce(T, &S) -> Result<(), E>,
+    K: FnOnce(E),
+{
+    if let Err(error) = callback(value, state) {
+        sink(error);
+    }
+}
+
+impl<T, S, E, F, K> FallibleScopeGuard<T, S, F, K>
+where
+    F: FnOnce(T, &S) -> Result<(), E>,
+    K: FnOnce(E),
+{
+    /// Create a new fallible stated scope guard.
+    ///
+    /// The `callback` takes current value and state as parameter, and when it
+    /// fails during dropping, the error is passed to `sink`.
+    pub fn new(value: T, state: S, callback: F, sink: K) -> Self {
+        Self {
+            guard: ScopeGuard::new((value, callback, sink), state, report::<T, S, E, F, K>),
+        }
+    }
+
+    /// Set current state to `state`
+    pub fn set_state(&mut self, state: S) {
+        self.guard.set_state(state);
+    }
+
+    /// Finish the scope guard now, calling the callback with current value and
+    /// state as parameter, and return its result. The error sink is not used.
+    pub fn finish(self) -> Result<(), E> {
+        let ((value, callback, _sink), state, _) = self.guard.into_parts();
+        callback(value, &state)
+    }
+
+    /// Defuse the scope guard, and take the inner value out without calling the callback.
+    pub fn into_inner(self) -> T {
+        self.guard.into_inner().0
+    }
+
+    /// Defuse the scope guard, and take the inner value, state, callback and
+    /// error sink out without calling the callback.
+    pub fn into_parts(self) -> (T, S, F, K) {
+        let ((value, callback, sink), state, _) = self.guard.into_parts();
+        (value, state, callback, sink)
+    }
+}
+
+impl<T, S, F, K> Deref for FallibleScopeGuard<T, S, F, K> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard.0
+    }
+}
+
+impl<T, S, F, K> DerefMut for FallibleScopeGuard<T, S, F, K> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard.0
+    }
+}
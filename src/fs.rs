@@ -0,0 +1,359 @@
+//! Guarded filesystem operations.
+//!
+//! File and directory operations are the most common usage of stated scope
+//! guards. This module provides operations which return an [`FsGuard`]
+//! reverting the operation when dropped unless committed:
+//!
+//! * [`create_dir_all`]: created directories are removed.
+//! * [`write()`]: the contents are written to a temporary file, which atomically
+//!   replaces the target by renaming, and the previous file is restored from a backup.
+//! * [`rename`]: the path is renamed back, and a replaced target is restored from a backup.
+//! * [`remove`]: the path is moved to a backup, which is moved back.
+//!
+//! The parent directories of renamed, created and removed paths are synced, so
+//! that the operations are durable once the functions return. If syncing
+//! fails, the operation is reverted and the error is returned.
+//!
+//! Backups are removed once committed. Errors of reverting or removing backups
+//! are discarded when the guard is dropped, and can be retrieved by
+//! [`finish`][crate::ScopeGuard::finish].
+//!
+//! Operations compose into a single [`FsTransaction`], which reverts all of
+//! them in reverse order unless committed.
+//!
+//! ```
+//! use std::fs;
+//! use std::io;
+//! use stated_scope_guard::fs::FsTransaction;
+//!
+//! # fn main() -> io::Result<()> {
+//! let dir = std::env::temp_dir().join(format!("fs-doc-{}", std::process::id()));
+//! fs::create_dir_all(&dir)?;
+//! fs::write(dir.join("config"), "old")?;
+//!
+//! let install = |fail: bool| -> io::Result<()> {
+//!     let mut transaction = FsTransaction::new();
+//!     transaction.create_dir_all(dir.join("logs/app"))?;
+//!     transaction.write(dir.join("config"), "new")?;
+//!     if fail {
+//!         return Err(io::Error::other("cannot start service"));
+//!     }
+//!     transaction.commit()
+//! };
+//!
+//! assert!(install(true).is_err());
+//! assert!(!dir.join("logs").exists());
+//! assert_eq!(fs::read_to_string(dir.join("config"))?, "old");
+//!
+//! install(false)?;
+//! assert!(dir.join("logs/app").is_dir());
+//! assert_eq!(fs::read_to_string(dir.join("config"))?, "new");
+//! # fs::remove_dir_all(&dir)
+//! # }
+//! ```
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::vec::Vec;
+
+use crate::journal::sync_parent;
+use crate::savepoint::{Savepoint, UndoLog};
+use crate::stack::GuardStack;
+use crate::{CommitState, Outcome, ScopeGuard, StatedDrop};
+
+/// Performed filesystem operation, which knows how to revert or settle itself
+pub struct FsOp(Op);
+
+/// Filesystem operation, together with what is needed to revert it
+enum Op {
+    /// Directories created, from the outermost to the innermost
+    CreateDirs(Vec<PathBuf>),
+    /// File written at `path`, whose previous version is kept at `backup`
+    Write {
+        path: PathBuf,
+        backup: Option<PathBuf>,
+    },
+    /// `from` renamed to `to`, whose previous version is kept at `backup`
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+        backup: Option<PathBuf>,
+    },
+    /// `path` removed by moving it to `backup`
+    Remove { path: PathBuf, backup: PathBuf },
+}
+
+impl FsOp {
+    /// Revert the operation.
+    fn revert(self) -> io::Result<()> {
+        match self.0 {
+            Op::CreateDirs(dirs) => {
+                let mut result = Ok(());
+                for dir in dirs.iter().rev() {
+                    result = result.and(fs::remove_dir(dir));
+                }
+                result
+            }
+            Op::Write { path, backup } => match backup {
+                Some(backup) => fs::rename(backup, path),
+                None => fs::remove_file(path),
+            },
+            Op::Rename { from, to, backup } => {
+                fs::rename(&to, from)?;
+                match backup {
+                    Some(backup) => fs::rename(backup, to),
+                    None => Ok(()),
+                }
+            }
+            Op::Remove { path, backup } => fs::rename(backup, path),
+        }
+    }
+
+    /// Remove the backup of a committed operation.
+    fn settle(self) -> io::Result<()> {
+        match self.0 {
+            Op::CreateDirs(_) => Ok(()),
+            Op::Write { backup, .. } | Op::Rename { backup, .. } => match backup {
+                Some(backup) => fs::remove_file(backup),
+                None => Ok(()),
+            },
+            Op::Remove { backup, .. } => remove_any(&backup),
+        }
+    }
+}
+
+/// Callback of [`FsGuard`], which settles the operation if committed, and
+/// reverts it otherwise
+pub struct Settle;
+
+impl<S: CommitState> StatedDrop<FsOp, S> for Settle {
+    type Output = io::Result<()>;
+
+    fn call(self, op: FsOp, state: &S) -> io::Result<()> {
+        if state.is_committed() {
+            op.settle()
+        } else {
+            op.revert()
+        }
+    }
+}
+
+/// Stated scope guard of a filesystem operation, which is reverted when
+/// dropped unless committed
+pub type FsGuard<S = Outcome> = ScopeGuard<FsOp, S, Settle>;
+
+/// Create a guard of `op` with [`Outcome::Rollback`] state.
+fn guard(op: Op) -> FsGuard {
+    ScopeGuard::with_handler(FsOp(op), Outcome::Rollback, Settle)
+}
+
+/// Remove a file or a directory with its contents.
+fn remove_any(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Unique sibling path of `path`, used for temporary files and backups
+fn sibling(path: &Path, tag: &str) -> io::Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut sibling = OsString::from(".");
+    sibling.push(name);
+    sibling.push(std::format!(
+        ".{tag}-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    Ok(path.with_file_name(sibling))
+}
+
+/// Keep a copy of an existing `path` at a backup path, without moving it.
+fn backup(path: &Path) -> io::Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let backup = sibling(path, "backup")?;
+    if fs::hard_link(path, &backup).is_err() {
+        fs::copy(path, &backup)?;
+    }
+    Ok(Some(backup))
+}
+
+/// Recursively create a directory and all of its missing parents, and sync
+/// the parent of every created directory, from the outermost to the innermost.
+/// The guard removes the created directories, from the innermost to the
+/// outermost.
+pub fn create_dir_all(path: impl AsRef<Path>) -> io::Result<FsGuard> {
+    let path = path.as_ref();
+    let mut missing = Vec::new();
+    let mut ancestor = Some(path);
+    while let Some(dir) = ancestor.filter(|dir| !dir.as_os_str().is_empty() && !dir.exists()) {
+        missing.push(dir.to_path_buf());
+        ancestor = dir.parent();
+    }
+    missing.reverse();
+    fs::create_dir_all(path)?;
+    let synced: io::Result<()> = missing.iter().try_for_each(|dir| sync_parent(dir));
+    let guard = guard(Op::CreateDirs(missing));
+    synced?;
+    Ok(guard)
+}
+
+/// Write `contents` to a temporary file, and atomically rename it to `path`.
+/// The guard restores the previous file, or removes the file if there was none.
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<FsGuard> {
+    let path = path.as_ref();
+    let temp = sibling(path, "tmp")?;
+    let written = fs::write(&temp, contents).and_then(|()| fs::File::open(&temp)?.sync_all());
+    if let Err(error) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(error);
+    }
+    let backup = match backup(path) {
+        Ok(backup) => backup,
+        Err(error) => {
+            let _ = fs::remove_file(&temp);
+            return Err(error);
+        }
+    };
+    if let Err(error) = fs::rename(&temp, path) {
+        let _ = fs::remove_file(&temp);
+        if let Some(backup) = backup {
+            let _ = fs::remove_file(backup);
+        }
+        return Err(error);
+    }
+    let guard = guard(Op::Write {
+        path: path.to_path_buf(),
+        backup,
+    });
+    sync_parent(path)?;
+    Ok(guard)
+}
+
+/// Rename `from` to `to`. The guard renames it back, and restores the
+/// previous `to` if it was replaced.
+pub fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<FsGuard> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let backup = backup(to)?;
+    if let Err(error) = fs::rename(from, to) {
+        if let Some(backup) = backup {
+            let _ = fs::remove_file(backup);
+        }
+        return Err(error);
+    }
+    let guard = guard(Op::Rename {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+        backup,
+    });
+    sync_parent(from)?;
+    sync_parent(to)?;
+    Ok(guard)
+}
+
+/// Remove a file or a directory with its contents, by moving it to a backup.
+/// The guard moves it back, and the backup is removed once committed.
+pub fn remove(path: impl AsRef<Path>) -> io::Result<FsGuard> {
+    let path = path.as_ref();
+    let backup = sibling(path, "removed")?;
+    fs::rename(path, &backup)?;
+    let guard = guard(Op::Remove {
+        path: path.to_path_buf(),
+        backup,
+    });
+    sync_parent(path)?;
+    Ok(guard)
+}
+
+/// Transaction of filesystem operations, which reverts all of them in reverse
+/// order when dropped unless committed
+///
+/// It is built on [`GuardStack`], and supports [`Savepoint`]s.
+pub struct FsTransaction {
+    stack: GuardStack<'static, Outcome, io::Result<()>>,
+}
+
+impl FsTransaction {
+    /// Create a new empty transaction.
+    pub fn new() -> Self {
+        Self {
+            stack: GuardStack::new(Outcome::Rollback),
+        }
+    }
+
+    /// Take over the operation guarded by `guard`, which is then reverted or
+    /// settled together with the transaction.
+    pub fn push(&mut self, guard: FsGuard) {
+        let (op, _, settle) = guard.into_parts();
+        self.stack.push(op, move |op, state| settle.call(op, state));
+    }
+
+    /// Perform [`create_dir_all`] in this transaction.
+    pub fn create_dir_all(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.push(create_dir_all(path)?);
+        Ok(())
+    }
+
+    /// Perform [`write()`] in this transaction.
+    pub fn write(&mut self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+        self.push(write(path, contents)?);
+        Ok(())
+    }
+
+    /// Perform [`rename`] in this transaction.
+    pub fn rename(&mut self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+        self.push(rename(from, to)?);
+        Ok(())
+    }
+
+    /// Perform [`remove`] in this transaction.
+    pub fn remove(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.push(remove(path)?);
+        Ok(())
+    }
+
+    /// Create a savepoint, which reverts the operations performed after it
+    /// when dropped, unless released.
+    pub fn savepoint(&mut self) -> Savepoint<'_, Self> {
+        let mark = self.stack.len();
+        Savepoint::new(self, mark)
+    }
+
+    /// Commit all operations, and remove their backups. The first error of
+    /// removing backups is returned.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.stack.set_state(Outcome::Commit);
+        self.stack.finish().into_iter().collect()
+    }
+
+    /// Revert all operations in reverse order, which is the same as dropping,
+    /// but returns the first error of reverting.
+    pub fn rollback(self) -> io::Result<()> {
+        self.stack.finish().into_iter().collect()
+    }
+}
+
+impl Default for FsTransaction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoLog for FsTransaction {
+    /// Number of performed operations
+    type Mark = usize;
+
+    fn rollback_to(&mut self, mark: usize) {
+        self.stack.rollback_to((mark, Outcome::Rollback));
+    }
+}
@@ -0,0 +1,425 @@
+//! Stated scope guard with asynchronous cleanup.
+//!
+//! [`Drop::drop`] is synchronous, while many cleanups are asynchronous, such as
+//! deleting resources through RPC, or flushing asynchronous writers.
+//! [`AsyncScopeGuard`] takes a callback returning a [`Future`]. The cleanup can
+//! be awaited by [`finish`][AsyncScopeGuard::finish], and when the guard is
+//! dropped synchronously, the future is handed to a [`Spawn`] implementor,
+//! which may spawn it on an executor, or run it by a blocking executor such as
+//! `BlockOn` with the `std` feature enabled.
+//!
+//! In asynchronous code, a common failure is not an error, but cancellation:
+//! the future is dropped at an `.await` point, such as by a timeout. [`GuardedFuture`]
+//! wraps a future together with a stated scope guard, and tracks whether the future
+//! is completed, fails or is dropped before completion, which is passed to the
+//! callback as [`FutureState`].
+//!
+//! This module does not depend on any specific executor.
+//!
+//! ```
+//! # #[cfg(feature = "std")]
+//! # {
+//! use std::cell::RefCell;
+//! use stated_scope_guard::future::{block_on, AsyncScopeGuard, BlockOn};
+//!
+//! let deleted = RefCell::new(Vec::new());
+//! let delete = |name, committed: &bool| {
+//!     let committed = *committed;
+//!     let deleted = &deleted;
+//!     async move {
+//!         if !committed {
+//!             deleted.borrow_mut().push(name);
+//!         }
+//!     }
+//! };
+//!
+//! block_on(async {
+//!     let guard = AsyncScopeGuard::new("log dir", false, delete, BlockOn);
+//!     guard.finish().await;
+//! });
+//! {
+//!     // Dropped synchronously, so the cleanup is run by `BlockOn`
+//!     let _guard = AsyncScopeGuard::new("user account", false, delete, BlockOn);
+//! }
+//! assert_eq!(deleted.into_inner(), ["log dir", "user account"]);
+//! # }
+//! ```
+
+use core::future::Future;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+#[cfg(feature = "std")]
+use core::task::Waker;
+use core::task::{Context, Poll};
+
+use crate::{ScopeGuard, StatedDrop};
+
+/// Fallback of [`AsyncScopeGuard`] to deal with the cleanup future when the
+/// guard is dropped synchronously
+///
+/// It is implemented for all closures taking the future as parameter, which
+/// may spawn the future on an executor.
+///
+/// ```
+/// # #[cfg(feature = "std")]
+/// # {
+/// use std::cell::RefCell;
+/// use stated_scope_guard::future::{block_on, AsyncScopeGuard};
+///
+/// let deleted = RefCell::new(Vec::new());
+/// let queue = RefCell::new(Vec::new());
+/// {
+///     let _guard = AsyncScopeGuard::new(
+///         "log dir",
+///         false,
+///         |name, _| {
+///             let deleted = &deleted;
+///             async move { deleted.borrow_mut().push(name) }
+///         },
+///         |future| queue.borrow_mut().push(future),
+///     );
+/// }
+/// // The cleanup is queued, but not run yet
+/// assert!(deleted.borrow().is_empty());
+/// for future in queue.into_inner() {
+///     block_on(future);
+/// }
+/// assert_eq!(deleted.into_inner(), ["log dir"]);
+/// # }
+/// ```
+pub trait Spawn<Fut> {
+    /// Deal with the cleanup `future`.
+    fn spawn(self, future: Fut);
+}
+
+impl<Fut, F> Spawn<Fut> for F
+where
+    F: FnOnce(Fut),
+{
+    fn spawn(self, future: Fut) {
+        self(future)
+    }
+}
+
+/// Stated scope guard with asynchronous cleanup
+pub struct AsyncScopeGuard<T, S, F, D>
+where
+    Cleanup<F, D>: StatedDrop<T, S>,
+{
+    guard: ScopeGuard<T, S, Cleanup<F, D>>,
+}
+
+/// Callback of the inner [`ScopeGuard`] of [`AsyncScopeGuard`], which calls
+/// the callback, and hands the cleanup future to the spawner
+pub struct Cleanup<F, D> {
+    callback: F,
+    spawner: D,
+}
+
+impl<T, S, F, D, Fut> StatedDrop<T, S> for Cleanup<F, D>
+where
+    F: FnOnce(T, &S) -> Fut,
+    Fut: Future,
+    D: Spawn<Fut>,
+{
+    type Output = ();
+
+    fn call(self, value: T, state: &S) {
+        self.spawner.spawn((self.callback)(value, state));
+    }
+}
+
+impl<T, S, F, D, Fut> AsyncScopeGuard<T, S, F, D>
+where
+    F: FnOnce(T, &S) -> Fut,
+    Fut: Future,
+    D: Spawn<Fut>,
+{
+    /// Create a new stated scope guard with asynchronous cleanup.
+    ///
+    /// The `callback` takes current value and state as parameter, and returns
+    /// the cleanup future. When the guard is dropped without
+    /// [`finish`][AsyncScopeGuard::finish], the future is handed to `spawner`.
+    pub fn new(value: T, state: S, callback: F, spawner: D) -> Self {
+        Self {
+            guard: ScopeGuard::with_handler(value, state, Cleanup { callback, spawner }),
+        }
+    }
+
+    /// Set current state to `state`
+    pub fn set_state(&mut self, state: S) {
+        self.guard.set_state(state);
+    }
+
+    /// Finish the scope guard now, calling the callback with current value and
+    /// state as parameter, and await the cleanup future. The spawner is not used.
+    pub async fn finish(self) -> Fut::Output {
+        let (value, state, cleanup) = self.guard.into_parts();
+        (cleanup.callback)(value, &state).await
+    }
+
+    /// Defuse the scope guard, and take the inner value out without calling the callback.
+    pub fn into_inner(self) -> T {
+        self.guard.into_inner()
+    }
+
+    /// Defuse the scope guard, and take the inner value, state, callback and
+    /// spawner out without calling the callback.
+    pub fn into_parts(self) -> (T, S, F, D) {
+        let (value, state, Cleanup { callback, spawner }) = self.guard.into_parts();
+        (value, state, callback, spawner)
+    }
+}
+
+impl<T, S, F, D> Deref for AsyncScopeGuard<T, S, F, D>
+where
+    Cleanup<F, D>: StatedDrop<T, S>,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T, S, F, D> DerefMut for AsyncScopeGuard<T, S, F, D>
+where
+    Cleanup<F, D>: StatedDrop<T, S>,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+/// State of a future wrapped by [`GuardedFuture`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FutureState {
+    /// The future is dropped before completion
+    Cancelled,
+    /// The future is completed successfully
+    Completed,
+    /// The future is completed with an error
+    Failed,
+}
+
+/// Output of a future, which can tell whether the future succeeds
+///
+/// Other outputs can be classified by a closure passed to
+/// [`GuardedFuture::with_classifier`].
+pub trait FutureOutput {
+    /// Whether the future succeeds with this output
+    fn is_success(&self) -> bool;
+}
+
+impl<T, E> FutureOutput for Result<T, E> {
+    fn is_success(&self) -> bool {
+        self.is_ok()
+    }
+}
+
+impl<T> FutureOutput for Option<T> {
+    fn is_success(&self) -> bool {
+        self.is_some()
+    }
+}
+
+impl FutureOutput for () {
+    fn is_success(&self) -> bool {
+        true
+    }
+}
+
+/// Future wrapper passing [`FutureState`] to the callback of a stated scope guard
+///
+/// When the inner future is completed, the callback is called with
+/// [`Completed`][FutureState::Completed] or [`Failed`][FutureState::Failed]
+/// according to the output, which is classified by [`FutureOutput`], or by a
+/// classifier passed to [`with_classifier`][GuardedFuture::with_classifier].
+/// When the wrapper is dropped before completion, the inner future is dropped
+/// first, and then the callback is called with
+/// [`Cancelled`][FutureState::Cancelled].
+///
+/// ```
+/// # #[cfg(feature = "std")]
+/// # {
+/// use std::cell::Cell;
+/// use std::future::{pending, ready};
+/// use stated_scope_guard::future::{block_on, FutureState, GuardedFuture};
+///
+/// let dropped_with = Cell::new(None);
+/// let output = block_on(GuardedFuture::new(ready(Err::<(), _>("failed")), (), |_, state| {
+///     dropped_with.set(Some(*state));
+/// }));
+/// assert_eq!(output, Err("failed"));
+/// assert_eq!(dropped_with.get(), Some(FutureState::Failed));
+///
+/// // Dropped before completion
+/// drop(GuardedFuture::new(pending::<()>(), (), |_, state| {
+///     dropped_with.set(Some(*state));
+/// }));
+/// assert_eq!(dropped_with.get(), Some(FutureState::Cancelled));
+/// # }
+/// ```
+pub struct GuardedFuture<Fut, T, F, K = fn(&<Fut as Future>::Output) -> bool>
+where
+    Fut: Future,
+    F: StatedDrop<T, FutureState>,
+{
+    /// Inner future, which is declared before `guard` so that it is dropped first
+    future: Fut,
+    /// Guard of the value together with the classifier of the output, which is
+    /// `None` after completion
+    guard: Option<(ScopeGuard<T, FutureState, F>, K)>,
+}
+
+impl<Fut, T, F> GuardedFuture<Fut, T, F>
+where
+    Fut: Future,
+    Fut::Output: FutureOutput,
+    F: StatedDrop<T, FutureState>,
+{
+    /// Wrap `future` together with a stated scope guard of `value`, whose
+    /// `callback` is a closure or function.
+    pub fn new<R>(future: Fut, value: T, callback: F) -> Self
+    where
+        F: FnOnce(T, &FutureState) -> R,
+    {
+        Self::with_handler(future, value, callback)
+    }
+
+    /// Wrap `future` together with a stated scope guard of `value`, whose
+    /// callback is any implementor of [`StatedDrop`].
+    pub fn with_handler(future: Fut, value: T, handler: F) -> Self {
+        Self {
+            future,
+            guard: Some((
+                ScopeGuard::with_handler(value, FutureState::Cancelled, handler),
+                FutureOutput::is_success,
+            )),
+        }
+    }
+}
+
+impl<Fut, T, F, K> GuardedFuture<Fut, T, F, K>
+where
+    Fut: Future,
+    F: StatedDrop<T, FutureState>,
+    K: FnOnce(&Fut::Output) -> bool,
+{
+    /// Wrap `future` together with a stated scope guard of `value`, whose
+    /// `callback` is a closure or function. The output of `future` is
+    /// successful if `classify` returns `true`, which allows outputs not
+    /// implementing [`FutureOutput`].
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// use std::cell::Cell;
+    /// use std::future::ready;
+    /// use stated_scope_guard::future::{block_on, FutureState, GuardedFuture};
+    ///
+    /// let dropped_with = Cell::new(None);
+    /// let callback = |_, state: &FutureState| dropped_with.set(Some(*state));
+    ///
+    /// let output = block_on(GuardedFuture::with_classifier(ready(0u32), (), callback, |&written| {
+    ///     written > 0
+    /// }));
+    /// assert_eq!(output, 0);
+    /// assert_eq!(dropped_with.get(), Some(FutureState::Failed));
+    ///
+    /// // Every completion is a success
+    /// let future = GuardedFuture::with_classifier(ready(String::new()), (), callback, |_| true);
+    /// block_on(future);
+    /// assert_eq!(dropped_with.get(), Some(FutureState::Completed));
+    /// # }
+    /// ```
+    pub fn with_classifier<R>(future: Fut, value: T, callback: F, classify: K) -> Self
+    where
+        F: FnOnce(T, &FutureState) -> R,
+    {
+        Self {
+            future,
+            guard: Some((
+                ScopeGuard::new(value, FutureState::Cancelled, callback),
+                classify,
+            )),
+        }
+    }
+}
+
+impl<Fut, T, F, K> Future for GuardedFuture<Fut, T, F, K>
+where
+    Fut: Future,
+    F: StatedDrop<T, FutureState>,
+    K: FnOnce(&Fut::Output) -> bool,
+{
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is structurally pinned, and is never moved, while
+        // `guard` is not structurally pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        // SAFETY: see above
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        let output = match future.poll(cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => return Poll::Pending,
+        };
+        if let Some((mut guard, classify)) = this.guard.take() {
+            guard.set_state(if classify(&output) {
+                FutureState::Completed
+            } else {
+                FutureState::Failed
+            });
+        }
+        Poll::Ready(output)
+    }
+}
+
+/// Waker unparking the thread running [`block_on`]
+#[cfg(feature = "std")]
+struct ThreadWaker(std::thread::Thread);
+
+#[cfg(feature = "std")]
+impl std::task::Wake for ThreadWaker {
+    fn wake(self: std::sync::Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &std::sync::Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Run `future` to completion on current thread, parking the thread while
+/// the future is pending.
+///
+/// This is a minimal blocking executor, which is enough for cleanups.
+#[cfg(feature = "std")]
+pub fn block_on<Fut: Future>(future: Fut) -> Fut::Output {
+    let mut future = core::pin::pin!(future);
+    let waker = Waker::from(std::sync::Arc::new(ThreadWaker(std::thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+/// Spawner running the cleanup future to completion by [`block_on`] when
+/// [`AsyncScopeGuard`] is dropped
+///
+/// It blocks the dropping thread, so it shall not be used inside an executor
+/// which does not allow blocking.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockOn;
+
+#[cfg(feature = "std")]
+impl<Fut: Future> Spawn<Fut> for BlockOn {
+    fn spawn(self, future: Fut) {
+        block_on(future);
+    }
+}
@@ -0,0 +1,62 @@
+//! Closure-scoped stated scope guard.
+//!
+//! The most common usage of [`ScopeGuard`] is: create the guard, run fallible
+//! steps with `?`, and set the state to a committed one as the last line. It is
+//! easy to forget the last line. [`guarded`] takes the fallible steps as a closure,
+//! and decides the final state from its outcome: `Ok` means commit, while `Err`
+//! or a panic means rollback.
+//!
+//! ```
+//! use std::cell::Cell;
+//! use stated_scope_guard::guarded::guarded_dismissible;
+//!
+//! let deleted = Cell::new(false);
+//! let result: Result<(), &str> = guarded_dismissible(
+//!     "log dir",
+//!     |_| deleted.set(true),
+//!     |_log_dir| Err("cannot create user account"),
+//! );
+//! assert!(result.is_err());
+//! assert!(deleted.get());
+//! ```
+
+use crate::dismissible::new_dismissible;
+use crate::ScopeGuard;
+
+/// Run `body` with `value` guarded by a stated scope guard.
+///
+/// The guard is created with `rollback` state, and its state is set to `commit`
+/// only if `body` returns `Ok`. Since `body` only has access to the value, not
+/// the guard, the state cannot be set anywhere else, and a panic in `body` will
+/// always drop the guard with `rollback` state.
+pub fn guarded<T, S, F, B, R, E>(
+    value: T,
+    rollback: S,
+    commit: S,
+    callback: F,
+    body: B,
+) -> Result<R, E>
+where
+    F: FnOnce(T, &S),
+    B: FnOnce(&mut T) -> Result<R, E>,
+{
+    let mut guard = ScopeGuard::new(value, rollback, callback);
+    let result = body(&mut guard)?;
+    guard.set_state(commit);
+    Ok(result)
+}
+
+/// Run `body` with `value` guarded by a dismissible stated scope guard.
+///
+/// The guard is dismissed only if `body` returns `Ok`, otherwise `callback`
+/// is called with `value`, even if `body` panics.
+pub fn guarded_dismissible<T, F, B, R, E>(value: T, callback: F, body: B) -> Result<R, E>
+where
+    F: FnOnce(T),
+    B: FnOnce(&mut T) -> Result<R, E>,
+{
+    let mut guard = new_dismissible(value, callback);
+    let result = body(&mut guard)?;
+    guard.dismiss();
+    Ok(result)
+}
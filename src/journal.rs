@@ -0,0 +1,358 @@
+//! Crash-safe rollback journal.
+//!
+//! If the process is killed halfway, the callbacks of in-memory scope guards
+//! never run, and the partial resources leak. [`JournaledTransaction`] writes
+//! an undo record to an append-only journal file, and syncs it to disk *before*
+//! the forward action is performed. If the process crashes, [`recover`] replays
+//! the outstanding undo records on the next start.
+//!
+//! Every frame of the journal carries a checksum of its header and another one
+//! of its payload, so that a torn write at the end of the journal is detected
+//! and ignored, while a corrupted frame in the middle, including a corrupted
+//! length, is reported as an error instead of being decoded. If appending a
+//! frame fails, the journal is truncated back to its previous length, or the
+//! transaction refuses further records if even that fails.
+//!
+//! Committing appends a commit marker to the journal and syncs it, which is the
+//! atomic point of the transaction, and then removes the journal file. A journal
+//! ending with an incomplete record, which is written when crashing before the
+//! forward action, is tolerated and the incomplete record is ignored.
+//!
+//! Since a crash may also happen during rollback or recovery, undo records may
+//! be replayed more than once, and shall be idempotent.
+//!
+//! ```
+//! use std::fs;
+//! use std::io;
+//! use std::path::PathBuf;
+//! use stated_scope_guard::journal::{recover, JournaledTransaction, UndoRecord};
+//!
+//! struct RemoveFile(PathBuf);
+//!
+//! impl UndoRecord for RemoveFile {
+//!     fn encode(&self) -> Vec<u8> {
+//!         self.0.to_str().unwrap().as_bytes().to_vec()
+//!     }
+//!
+//!     fn decode(bytes: &[u8]) -> io::Result<Self> {
+//!         let path = std::str::from_utf8(bytes).map_err(io::Error::other)?;
+//!         Ok(Self(PathBuf::from(path)))
+//!     }
+//!
+//!     fn undo(self) -> io::Result<()> {
+//!         match fs::remove_file(&self.0) {
+//!             Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+//!             _ => Ok(()),
+//!         }
+//!     }
+//! }
+//!
+//! # fn main() -> io::Result<()> {
+//! let dir = std::env::temp_dir().join(format!("journal-doc-{}", std::process::id()));
+//! fs::create_dir_all(&dir)?;
+//! let journal_path = dir.join("journal");
+//! let file_path = dir.join("file");
+//!
+//! let mut transaction = JournaledTransaction::create(&journal_path)?;
+//! transaction.record(RemoveFile(file_path.clone()))?;
+//! fs::write(&file_path, "content")?;
+//! // Simulate a crash, so that no in-memory rollback happens
+//! std::mem::forget(transaction);
+//! assert!(file_path.exists());
+//!
+//! // On the next start
+//! assert_eq!(recover::<RemoveFile>(&journal_path)?, 1);
+//! assert!(!file_path.exists());
+//! assert!(!journal_path.exists());
+//! # fs::remove_dir_all(&dir)
+//! # }
+//! ```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::vec::Vec;
+
+use crate::dismissible::{DismissibleCallback, DismissibleScopeGuard};
+
+/// Serializable undo record of a journaled forward action
+pub trait UndoRecord: Sized {
+    /// Encode this record to bytes.
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decode a record from bytes produced by [`encode`][UndoRecord::encode].
+    fn decode(bytes: &[u8]) -> io::Result<Self>;
+
+    /// Undo the forward action.
+    ///
+    /// This may be called more than once for the same forward action, so it
+    /// shall be idempotent.
+    fn undo(self) -> io::Result<()>;
+}
+
+/// Tag of a frame holding an undo record
+const RECORD_TAG: u8 = 0;
+/// Tag of a frame marking the transaction is committed
+const COMMIT_TAG: u8 = 1;
+/// Length of the frame header, which consists of a tag, a little-endian `u32`
+/// length of the payload, a little-endian `u32` checksum of the tag and length,
+/// and a little-endian `u32` checksum of the payload
+const HEADER_LEN: usize = 13;
+
+/// Update a CRC-32 state `crc` with `bytes`.
+fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// CRC-32 checksum of the tag and length of a frame
+fn header_checksum(tag: u8, len: [u8; 4]) -> u32 {
+    !crc32(crc32(!0, &[tag]), &len)
+}
+
+/// CRC-32 checksum of the payload of a frame
+fn payload_checksum(payload: &[u8]) -> u32 {
+    !crc32(!0, payload)
+}
+
+/// Error of a journal file which is not written by a [`JournaledTransaction`]
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Journal file and undo records written to it
+struct Journal<R> {
+    file: File,
+    path: PathBuf,
+    records: Vec<R>,
+    /// Length of the complete frames in the journal file
+    len: u64,
+    /// Whether a failed append could not be truncated, so that the journal
+    /// file may end with a partial frame
+    poisoned: bool,
+}
+
+impl<R: UndoRecord> Journal<R> {
+    /// Append a frame and sync it to disk.
+    ///
+    /// If it fails, the journal file is truncated back to its previous length,
+    /// so that no partial frame is followed by later frames. If truncating also
+    /// fails, the journal is poisoned, and all later appends fail.
+    fn append(&mut self, tag: u8, payload: &[u8]) -> io::Result<()> {
+        if self.poisoned {
+            return Err(io::Error::other(
+                "journal may end with a partial frame after a failed append",
+            ));
+        }
+        let len = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "undo record too large"))?
+            .to_le_bytes();
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.push(tag);
+        frame.extend_from_slice(&len);
+        frame.extend_from_slice(&header_checksum(tag, len).to_le_bytes());
+        frame.extend_from_slice(&payload_checksum(payload).to_le_bytes());
+        frame.extend_from_slice(payload);
+        if let Err(error) = self
+            .file
+            .write_all(&frame)
+            .and_then(|()| self.file.sync_data())
+        {
+            let truncated = self
+                .file
+                .set_len(self.len)
+                .and_then(|()| self.file.seek(SeekFrom::Start(self.len)))
+                .and_then(|_| self.file.sync_data());
+            self.poisoned = truncated.is_err();
+            return Err(error);
+        }
+        self.len += frame.len() as u64;
+        Ok(())
+    }
+
+    /// Undo all records in reverse order, and remove the journal file if all
+    /// of them succeed. Otherwise, the journal file is kept for [`recover`].
+    fn rollback(self) -> io::Result<()> {
+        undo_all(self.records)?;
+        remove_journal(&self.path)
+    }
+}
+
+/// Undo `records` in reverse order, and return the first error after trying
+/// all of them.
+fn undo_all<R: UndoRecord>(records: Vec<R>) -> io::Result<()> {
+    let mut result = Ok(());
+    for record in records.into_iter().rev() {
+        let undone = record.undo();
+        if result.is_ok() {
+            result = undone;
+        }
+    }
+    result
+}
+
+/// Remove the journal file, and sync its parent directory.
+fn remove_journal(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)?;
+    sync_parent(path)
+}
+
+/// Sync the parent directory of `path`, so that creation or removal of `path`
+/// is durable.
+pub(crate) fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Roll back the journal when the transaction is dropped without committing.
+fn rollback_journal<R: UndoRecord>(journal: Journal<R>) {
+    // There is no way to report errors in drop, and the journal file is kept
+    // for recovery if any undo record fails.
+    let _ = journal.rollback();
+}
+
+/// Transaction whose undo records are persisted to a journal file
+///
+/// When dropped without [`commit`][JournaledTransaction::commit], the undo
+/// records are undone in reverse order of recording.
+pub struct JournaledTransaction<R: UndoRecord> {
+    guard: JournalGuard<R>,
+}
+
+/// Guard of the journal, which rolls back the journal unless dismissed
+type JournalGuard<R> = DismissibleScopeGuard<Journal<R>, DismissibleCallback<fn(Journal<R>)>>;
+
+impl<R: UndoRecord> JournaledTransaction<R> {
+    /// Create a new transaction with a new journal file at `path`.
+    ///
+    /// It fails if the journal file already exists, in which case a previous
+    /// transaction has not finished, and [`recover`] shall be called first.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+        sync_parent(path)?;
+        let journal = Journal {
+            file,
+            path: path.to_path_buf(),
+            records: Vec::new(),
+            len: 0,
+            poisoned: false,
+        };
+        Ok(Self {
+            guard: DismissibleScopeGuard::new_dismissible(journal, rollback_journal),
+        })
+    }
+
+    /// Write `record` to the journal, and sync it to disk. The forward action
+    /// shall be performed after this function succeeds.
+    ///
+    /// If it fails, the forward action shall not be performed. The transaction
+    /// can still be used, unless the partially written record cannot be removed
+    /// from the journal, in which case all later records fail.
+    pub fn record(&mut self, record: R) -> io::Result<()> {
+        self.guard.append(RECORD_TAG, &record.encode())?;
+        self.guard.records.push(record);
+        Ok(())
+    }
+
+    /// Commit the transaction, and remove the journal file.
+    ///
+    /// Once the commit marker is synced to disk, the transaction is committed,
+    /// even if removing the journal file fails. If writing the commit marker
+    /// fails, the transaction is rolled back.
+    pub fn commit(self) -> io::Result<()> {
+        let mut journal = self.guard.into_inner();
+        if let Err(error) = journal.append(COMMIT_TAG, &[]) {
+            let _ = journal.rollback();
+            return Err(error);
+        }
+        remove_journal(&journal.path)
+    }
+
+    /// Roll back the transaction now, undoing all records in reverse order.
+    ///
+    /// The journal file is removed only if all records are undone successfully,
+    /// otherwise it is kept for [`recover`], and the first error is returned.
+    pub fn rollback(self) -> io::Result<()> {
+        self.guard.into_inner().rollback()
+    }
+}
+
+/// Recover from the journal file at `path` left by a crashed transaction, and
+/// return the number of undone records.
+///
+/// If there is no journal file, nothing is done. If the transaction has been
+/// committed, the journal file is just removed. Otherwise, all records are
+/// undone in reverse order, and the journal file is removed only if all of
+/// them succeed.
+pub fn recover<R: UndoRecord>(path: impl AsRef<Path>) -> io::Result<usize> {
+    let path = path.as_ref();
+    let mut bytes = Vec::new();
+    match File::open(path) {
+        Ok(mut file) => file.read_to_end(&mut bytes)?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error),
+    };
+
+    let mut records = Vec::new();
+    let mut rest = bytes.as_slice();
+    while !rest.is_empty() {
+        let Some(header) = rest.get(..HEADER_LEN) else {
+            // Incomplete header of the last frame, whose forward action has
+            // never been performed
+            break;
+        };
+        let tag = header[0];
+        let len = [header[1], header[2], header[3], header[4]];
+        let checksum = |at: usize| {
+            u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+        };
+        // The length is only trusted once the header is verified, so that a
+        // corrupted length cannot pass for an incomplete last frame
+        if header_checksum(tag, len) != checksum(5) {
+            return Err(invalid_data("corrupted journal frame header"));
+        }
+        let end = HEADER_LEN.saturating_add(u32::from_le_bytes(len) as usize);
+        let Some(payload) = rest.get(HEADER_LEN..end) else {
+            // Incomplete payload of the last frame
+            break;
+        };
+        if payload_checksum(payload) != checksum(9) {
+            if end == rest.len() {
+                // Torn payload of the last frame
+                break;
+            }
+            return Err(invalid_data("corrupted journal frame"));
+        }
+        match tag {
+            RECORD_TAG => records.push(R::decode(payload)?),
+            COMMIT_TAG => return remove_journal(path).map(|()| 0),
+            _ => return Err(invalid_data("invalid journal frame")),
+        }
+        rest = &rest[end..];
+    }
+
+    let count = records.len();
+    undo_all(records)?;
+    remove_journal(path)?;
+    Ok(count)
+}
@@ -1,14 +1,145 @@
 #![doc = include_str!("../README.md")]
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "alloc")]
+pub mod atomic;
+#[cfg(feature = "alloc")]
+pub mod boxed;
 pub mod dismissible;
+pub mod fallible;
+#[cfg(feature = "std")]
+pub mod fs;
+pub mod future;
+pub mod guarded;
+#[cfg(feature = "std")]
+pub mod journal;
+pub mod restore;
+#[cfg(feature = "alloc")]
+pub mod rollback;
+#[cfg(feature = "alloc")]
+pub mod saga;
+pub mod savepoint;
+pub mod shadow;
+pub mod shared;
+#[cfg(feature = "alloc")]
+pub mod stack;
+pub mod state_machine;
+#[cfg(feature = "alloc")]
+pub mod token;
+#[cfg(feature = "alloc")]
+pub mod two_phase;
+#[cfg(feature = "alloc")]
+pub mod tx;
+pub mod typestate;
+#[cfg(feature = "std")]
+pub mod undecided;
+#[cfg(feature = "std")]
+pub mod unwind;
 
+use core::mem::ManuallyDrop;
 use core::ops::{Deref, DerefMut};
+use core::ptr;
+
+/// Callback of a stated scope guard, which deals with the value according to the state
+///
+/// It is implemented for all closures and function pointers taking the value and
+/// state as parameter. Since the type of a closure cannot be named, a guard with
+/// a closure callback cannot be put in struct fields or type aliases without boxing.
+/// Implementing this trait for a named type, such as a zero-sized struct, or using
+/// a function pointer, makes the guard type fully nameable:
+///
+/// ```
+/// use stated_scope_guard::{ScopeGuard, StatedDrop};
+///
+/// # struct LogDir;
+/// # fn delete_log_dir(_: LogDir) {}
+/// struct DeleteLogDir;
+///
+/// impl StatedDrop<LogDir, bool> for DeleteLogDir {
+///     type Output = ();
+///
+///     fn call(self, log_dir: LogDir, committed: &bool) {
+///         if !*committed {
+///             delete_log_dir(log_dir);
+///         }
+///     }
+/// }
+///
+/// type LogDirGuard = ScopeGuard<LogDir, bool, DeleteLogDir>;
+/// type CountGuard = ScopeGuard<u32, bool, fn(u32, &bool)>;
+///
+/// struct Setup {
+///     log_dir: LogDirGuard,
+///     count: CountGuard,
+/// }
+///
+/// let setup = Setup {
+///     log_dir: ScopeGuard::with_handler(LogDir, false, DeleteLogDir),
+///     count: ScopeGuard::new(0, false, |_, _| {}),
+/// };
+/// ```
+pub trait StatedDrop<T, S> {
+    /// Return type of the callback
+    type Output;
+
+    /// Deal with `value` according to `state`.
+    fn call(self, value: T, state: &S) -> Self::Output;
+}
+
+impl<T, S, R, F> StatedDrop<T, S> for F
+where
+    F: FnOnce(T, &S) -> R,
+{
+    type Output = R;
+
+    fn call(self, value: T, state: &S) -> R {
+        self(value, state)
+    }
+}
+
+/// State telling whether the changes made under a guard are committed, or shall
+/// be rolled back
+///
+/// It is implemented for `bool`, where `true` means committed, and [`Outcome`].
+pub trait CommitState {
+    /// Whether the changes are committed
+    fn is_committed(&self) -> bool;
+}
+
+impl CommitState for bool {
+    fn is_committed(&self) -> bool {
+        *self
+    }
+}
+
+/// Outcome of the changes made under a guard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Outcome {
+    /// The changes shall be rolled back, which is the initial state
+    #[default]
+    Rollback,
+    /// The changes are committed
+    Commit,
+}
+
+impl CommitState for Outcome {
+    fn is_committed(&self) -> bool {
+        *self == Outcome::Commit
+    }
+}
 
 /// Stated scope guard
+///
+/// The callback may return a value, which can be retrieved by
+/// [`finish`][ScopeGuard::finish], and is discarded when the guard is dropped.
 pub struct ScopeGuard<T, S, F>
 where
-    F: FnOnce(T, &S),
+    F: StatedDrop<T, S>,
 {
     /// Inner value
     ///
@@ -20,7 +151,7 @@ where
     /// Callback function. It takes current value and state as parameter, and is expected to work as
     /// dealing `value` differently according to `state`.
     ///
-    /// This function will be called when [`ScopeGuard`] is dropped.
+    /// This function will be called when [`ScopeGuard`] is dropped or finished.
     ///
     /// Use `Option` here since the [`drop`][Drop::drop] method only supports `&mut self`,
     /// while we need to take ownership in `drop`.
@@ -29,28 +160,93 @@ where
 
 impl<T, S, F> ScopeGuard<T, S, F>
 where
-    F: FnOnce(T, &S),
+    F: StatedDrop<T, S>,
 {
     /// Create a new stated scope guard.
     ///
     /// The `value` passed into it can be derefed by [`Deref`] and [`DerefMut`] trait.
-    pub fn new(value: T, state: S, callback: F) -> Self {
+    ///
+    /// The `callback` shall be a closure or function, so that the types of its
+    /// parameters can be inferred. To use other implementors of [`StatedDrop`],
+    /// use [`with_handler`][ScopeGuard::with_handler] instead.
+    pub fn new<R>(value: T, state: S, callback: F) -> Self
+    where
+        F: FnOnce(T, &S) -> R,
+    {
+        Self::with_handler(value, state, callback)
+    }
+
+    /// Create a new stated scope guard with any implementor of [`StatedDrop`]
+    /// as callback.
+    pub fn with_handler(value: T, state: S, handler: F) -> Self {
         Self {
             value: Some(value),
             state,
-            callback: Some(callback),
+            callback: Some(handler),
         }
     }
 
     /// Set current state to `state`
+    ///
+    /// The state is changed unconditionally. For state types implementing
+    /// [`StateMachine`][state_machine::StateMachine], this bypasses the
+    /// declared transitions and the transition hook, which are only applied by
+    /// [`try_set_state`][ScopeGuard::try_set_state] and
+    /// [`set_state_checked`][ScopeGuard::set_state_checked].
     pub fn set_state(&mut self, state: S) {
         self.state = state;
     }
+
+    /// Finish the scope guard now, calling the callback with current value and
+    /// state as parameter, and return what the callback returns.
+    ///
+    /// This is useful when the cleanup shall happen at a well-defined point, or
+    /// its result, such as an error, needs to be propagated. The implicit drop is
+    /// then only a fallback for early returns.
+    ///
+    /// ```
+    /// use stated_scope_guard::ScopeGuard;
+    ///
+    /// fn cleanup(resource: u32, committed: bool) -> Result<(), u32> {
+    ///     if committed { Ok(()) } else { Err(resource) }
+    /// }
+    ///
+    /// let mut guard = ScopeGuard::new(42, false, |resource, committed| {
+    ///     cleanup(resource, *committed)
+    /// });
+    /// guard.set_state(true);
+    /// assert_eq!(guard.finish(), Ok(()));
+    /// ```
+    pub fn finish(self) -> F::Output {
+        let (value, state, callback) = self.into_parts();
+        callback.call(value, &state)
+    }
+
+    /// Defuse the scope guard, and take the inner value out without calling the callback.
+    ///
+    /// This is useful when the resource is built under a guard, and shall be
+    /// handed to a longer-lived owner once setup succeeds.
+    pub fn into_inner(self) -> T {
+        self.into_parts().0
+    }
+
+    /// Defuse the scope guard, and take the inner value, state and callback out
+    /// without calling the callback.
+    pub fn into_parts(self) -> (T, S, F) {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `value` is always `Some` until dropped, and `this` will never be dropped
+        let value = unsafe { this.value.take().unwrap_unchecked() };
+        // SAFETY: `callback` is always `Some` until dropped, and `this` will never be dropped
+        let callback = unsafe { this.callback.take().unwrap_unchecked() };
+        // SAFETY: `this` will never be dropped, so `state` is only read once
+        let state = unsafe { ptr::read(&this.state) };
+        (value, state, callback)
+    }
 }
 
 impl<T, S, F> Deref for ScopeGuard<T, S, F>
 where
-    F: FnOnce(T, &S),
+    F: StatedDrop<T, S>,
 {
     type Target = T;
 
@@ -62,7 +258,7 @@ where
 
 impl<T, S, F> DerefMut for ScopeGuard<T, S, F>
 where
-    F: FnOnce(T, &S),
+    F: StatedDrop<T, S>,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
         // SAFETY: `value` is always `Some` until dropped
@@ -72,15 +268,15 @@ where
 
 impl<T, S, F> Drop for ScopeGuard<T, S, F>
 where
-    F: FnOnce(T, &S),
+    F: StatedDrop<T, S>,
 {
     /// When dropping, the `callback` will be called with current `value`
-    /// and `state` as parameter.
+    /// and `state` as parameter, and its return value is discarded.
     fn drop(&mut self) {
         // SAFETY: `value` is always `Some` until dropped
         let value = unsafe { self.value.take().unwrap_unchecked() };
         // SAFETY: `callback` is always `Some` until dropped
         let callback = unsafe { self.callback.take().unwrap_unchecked() };
-        callback(value, &self.state);
+        callback.call(value, &self.state);
     }
 }
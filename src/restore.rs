@@ -0,0 +1,136 @@
+//! Stated scope guard restoring a mutably borrowed value on rollback.
+//!
+//! A very common usage of [`ScopeGuard`] is mutating a `&mut T`, and putting it
+//! back if something fails. [`RestoreGuard`] borrows the value, takes a snapshot
+//! when created, and derefs to the live value. When dropped with a state which is
+//! not [committed][CommitState::is_committed], the snapshot is restored.
+//!
+//! ```
+//! use stated_scope_guard::restore::RestoreGuard;
+//! use stated_scope_guard::Outcome;
+//!
+//! fn add_users(users: &mut Vec<&'static str>, new_users: &[&'static str]) -> Result<(), ()> {
+//!     let mut users = RestoreGuard::new(users, Outcome::Rollback);
+//!     for user in new_users {
+//!         if users.contains(user) {
+//!             return Err(());
+//!         }
+//!         users.push(user);
+//!     }
+//!     users.set_state(Outcome::Commit);
+//!     Ok(())
+//! }
+//!
+//! let mut users = vec!["alice"];
+//! assert!(add_users(&mut users, &["bob", "alice"]).is_err());
+//! assert_eq!(users, ["alice"]);
+//! assert!(add_users(&mut users, &["bob"]).is_ok());
+//! assert_eq!(users, ["alice", "bob"]);
+//! ```
+//!
+//! For types whose full clone is too expensive, a custom pair of snapshot and
+//! restore can be used by [`with_snapshot`][RestoreGuard::with_snapshot], such
+//! as only remembering the length of a vector which is only pushed to:
+//!
+//! ```
+//! use stated_scope_guard::restore::RestoreGuard;
+//!
+//! let mut log = vec!["started"];
+//! {
+//!     let mut log = RestoreGuard::with_snapshot(&mut log, false, Vec::len, Vec::truncate);
+//!     log.push("step 1");
+//!     log.push("step 2");
+//! }
+//! assert_eq!(log, ["started"]);
+//! ```
+
+use core::ops::{Deref, DerefMut};
+
+use crate::{CommitState, Outcome, ScopeGuard};
+
+/// Stated scope guard restoring a mutably borrowed value when dropped with a
+/// state which is not committed
+///
+/// The snapshot is of type `P`, and is restored by `F`. By default, the
+/// snapshot is a clone of the value, which is restored by assignment.
+pub struct RestoreGuard<'a, T, S = Outcome, P = T, F = fn(&mut T, T)> {
+    guard: Inner<'a, T, S, P, F>,
+}
+
+/// Inner [`ScopeGuard`] of [`RestoreGuard`]
+type Inner<'a, T, S, P, F> = ScopeGuard<(&'a mut T, P, F), S, fn((&'a mut T, P, F), &S)>;
+
+/// Restore the snapshot unless committed.
+fn rollback<T, S, P, F>((value, snapshot, restore): (&mut T, P, F), state: &S)
+where
+    S: CommitState,
+    F: FnOnce(&mut T, P),
+{
+    if !state.is_committed() {
+        restore(value, snapshot);
+    }
+}
+
+/// Restore a cloned snapshot by assignment.
+fn assign<T>(value: &mut T, snapshot: T) {
+    *value = snapshot;
+}
+
+impl<'a, T, S> RestoreGuard<'a, T, S>
+where
+    T: Clone,
+    S: CommitState,
+{
+    /// Create a new restore guard of `value` with initial `state`, whose
+    /// snapshot is a clone of `value`.
+    pub fn new(value: &'a mut T, state: S) -> Self {
+        let snapshot = value.clone();
+        Self::from_snapshot(value, state, snapshot, assign::<T>)
+    }
+}
+
+impl<'a, T, S, P, F> RestoreGuard<'a, T, S, P, F>
+where
+    S: CommitState,
+    F: FnOnce(&mut T, P),
+{
+    /// Create a new restore guard of `value` with initial `state`. The snapshot
+    /// is taken by `snapshot` now, and is restored by `restore` on rollback.
+    pub fn with_snapshot<G>(value: &'a mut T, state: S, snapshot: G, restore: F) -> Self
+    where
+        G: FnOnce(&T) -> P,
+    {
+        let snapshot = snapshot(value);
+        Self::from_snapshot(value, state, snapshot, restore)
+    }
+
+    fn from_snapshot(value: &'a mut T, state: S, snapshot: P, restore: F) -> Self {
+        Self {
+            guard: ScopeGuard::new((value, snapshot, restore), state, rollback::<T, S, P, F>),
+        }
+    }
+
+    /// Set current state to `state`
+    pub fn set_state(&mut self, state: S) {
+        self.guard.set_state(state);
+    }
+
+    /// Finish the restore guard now, restoring the snapshot unless committed.
+    pub fn finish(self) {
+        self.guard.finish()
+    }
+}
+
+impl<T, S, P, F> Deref for RestoreGuard<'_, T, S, P, F> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.guard.0
+    }
+}
+
+impl<T, S, P, F> DerefMut for RestoreGuard<'_, T, S, P, F> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut *self.guard.0
+    }
+}
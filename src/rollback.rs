@@ -0,0 +1,106 @@
+//! Compound error of a failed operation and its failed rollback.
+//!
+//! When a step of setup fails, and rolling back a previous step also fails,
+//! both errors are worth reporting. [`RollbackError`] carries the primary error
+//! together with every error collected during the rollback. It is usually
+//! produced by [`GuardStack::fail`][crate::stack::GuardStack::fail].
+//!
+//! With the `std` feature enabled, [`RollbackError`] implements `Error`, whose
+//! `source` is the primary error, so that error reporters walking the source
+//! chain will see the full story:
+//!
+//! ```
+//! # #[cfg(feature = "std")]
+//! # {
+//! use std::error::Error;
+//! use std::io;
+//! use stated_scope_guard::rollback::RollbackError;
+//!
+//! let error = RollbackError::new(
+//!     io::Error::other("cannot create network"),
+//!     vec![io::Error::other("cannot delete log dir")],
+//! );
+//! assert_eq!(
+//!     error.to_string(),
+//!     "operation failed, and its rollback failed: cannot delete log dir",
+//! );
+//! assert_eq!(error.source().unwrap().to_string(), "cannot create network");
+//! # }
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+/// Primary error of an operation, together with errors of its rollback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollbackError<E, R> {
+    /// The primary error, which triggers the rollback
+    error: E,
+    /// Errors collected during the rollback, in order of occurrence
+    rollback_errors: Vec<R>,
+}
+
+impl<E, R> RollbackError<E, R> {
+    /// Create a new rollback error from the primary `error` and the errors
+    /// collected during the rollback.
+    pub fn new(error: E, rollback_errors: Vec<R>) -> Self {
+        Self {
+            error,
+            rollback_errors,
+        }
+    }
+
+    /// The primary error
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    /// Errors collected during the rollback, in order of occurrence
+    pub fn rollback_errors(&self) -> &[R] {
+        &self.rollback_errors
+    }
+
+    /// Whether the rollback succeeds, i.e., no error is collected during the rollback
+    pub fn is_rolled_back(&self) -> bool {
+        self.rollback_errors.is_empty()
+    }
+
+    /// Take the primary error and errors of the rollback out.
+    pub fn into_parts(self) -> (E, Vec<R>) {
+        (self.error, self.rollback_errors)
+    }
+}
+
+impl<E, R> fmt::Display for RollbackError<E, R>
+where
+    R: fmt::Display,
+{
+    /// The primary error is not displayed, since it is the `source` of this
+    /// error.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rollback_errors.is_empty() {
+            return f.write_str("operation failed and was rolled back");
+        }
+        f.write_str("operation failed, and its rollback failed: ")?;
+        for (index, rollback_error) in self.rollback_errors.iter().enumerate() {
+            if index != 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{rollback_error}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E, R> Error for RollbackError<E, R>
+where
+    E: Error + 'static,
+    R: fmt::Debug + fmt::Display,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
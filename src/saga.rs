@@ -0,0 +1,407 @@
+//! Saga of forward steps and compensating actions.
+//!
+//! A multi-step setup, such as the `LogDir`, `UserAccount` and `UserNetwork`
+//! example in the crate documentation, is really a saga: each step has a
+//! compensation, which must run in reverse order when a later step fails.
+//! [`Saga`] declares steps as pairs of action and compensation. All of them
+//! take a shared context, through which the output of a step feeds later steps.
+//!
+//! The context is the only channel between steps: an output is typically
+//! stored in an `Option` field of the context, and unwrapped by later steps and
+//! compensations. Unlike chaining the steps by their types, nothing checks that
+//! an output is present when a later step reads it, but every completed step
+//! has left its output in the context, which is also recorded and restored by
+//! resumable runs.
+//!
+//! Running a saga returns a [`SagaReport`], which tells which steps ran, which
+//! were compensated, and which compensations failed. Compensations are also run
+//! if an action panics.
+//!
+//! ```
+//! use stated_scope_guard::saga::Saga;
+//!
+//! #[derive(Default)]
+//! struct Context {
+//!     log_dir: Option<String>,
+//!     user_account: Option<String>,
+//! }
+//!
+//! let mut context = Context::default();
+//! let report = Saga::new()
+//!     .step(
+//!         "log dir",
+//!         |context: &mut Context| {
+//!             context.log_dir = Some(String::from("/var/log/user"));
+//!             Ok(())
+//!         },
+//!         |context| {
+//!             context.log_dir = None;
+//!             Ok(())
+//!         },
+//!     )
+//!     .step(
+//!         "user account",
+//!         |context| {
+//!             let log_dir = context.log_dir.as_ref().unwrap();
+//!             context.user_account = Some(format!("user logging to {log_dir}"));
+//!             Ok(())
+//!         },
+//!         |_| Err("cannot delete user account"),
+//!     )
+//!     .step("user network", |_| Err("cannot create network"), |_| Ok(()))
+//!     .run(&mut context);
+//!
+//! assert_eq!(report.ran(), ["log dir", "user account"]);
+//! assert_eq!(report.compensated(), ["log dir"]);
+//! assert_eq!(report.compensation_errors(), [("user account", "cannot delete user account")]);
+//! assert_eq!(report.error(), Some(&("user network", "cannot create network")));
+//! assert!(context.log_dir.is_none());
+//! ```
+//!
+//! With [`run_resumable`][Saga::run_resumable], the progress is recorded by a
+//! [`SagaProgress`] after every step together with the context, which holds the
+//! outputs of completed steps. The progress may be persisted, so that an
+//! interrupted saga can be resumed by restoring the context and skipping
+//! completed actions.
+//!
+//! ```
+//! use stated_scope_guard::saga::{Checkpoint, Saga};
+//!
+//! #[derive(Clone, Default)]
+//! struct Context {
+//!     log_dir: Option<String>,
+//!     user_account: Option<String>,
+//! }
+//!
+//! // The first step has been completed before the process is interrupted, and
+//! // its output is recorded together with the progress
+//! let mut progress = Checkpoint::new(
+//!     1,
+//!     Context {
+//!         log_dir: Some(String::from("/var/log/user")),
+//!         ..Context::default()
+//!     },
+//! );
+//! // On the next start, the context is fresh
+//! let mut context = Context::default();
+//! let report = Saga::new()
+//!     .step(
+//!         "log dir",
+//!         |_: &mut Context| unreachable!("completed before interruption"),
+//!         |context| {
+//!             context.log_dir = None;
+//!             Ok(())
+//!         },
+//!     )
+//!     .step(
+//!         "user account",
+//!         |context| {
+//!             let log_dir = context.log_dir.as_ref().unwrap();
+//!             context.user_account = Some(format!("user logging to {log_dir}"));
+//!             Ok::<_, &str>(())
+//!         },
+//!         |_| Ok(()),
+//!     )
+//!     .run_resumable(&mut context, &mut progress);
+//! assert_eq!(report.ran(), ["user account"]);
+//! assert_eq!(progress.completed(), 2);
+//! assert_eq!(context.user_account.unwrap(), "user logging to /var/log/user");
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::rollback::RollbackError;
+use crate::{ScopeGuard, StatedDrop};
+
+/// Action or compensation of a saga step
+type Action<'a, C, E> = Box<dyn FnOnce(&mut C) -> Result<(), E> + 'a>;
+
+/// Step of a saga
+struct Step<'a, C, E> {
+    name: &'a str,
+    action: Action<'a, C, E>,
+    compensation: Action<'a, C, E>,
+}
+
+/// Saga of forward steps and compensating actions
+pub struct Saga<'a, C, E> {
+    steps: Vec<Step<'a, C, E>>,
+}
+
+/// Progress of a saga, i.e., the number of completed steps which are not
+/// compensated, together with the context they left
+///
+/// It is implemented by [`Checkpoint`] in memory, and can be implemented for
+/// types persisting the progress, so that an interrupted saga can be resumed.
+pub trait SagaProgress<C> {
+    /// Number of completed steps which are not compensated
+    fn completed(&self) -> usize;
+
+    /// Record the number of completed steps which are not compensated, together
+    /// with the context holding their outputs.
+    fn set_completed(&mut self, completed: usize, context: &C);
+
+    /// Restore the context recorded together with the completed steps.
+    fn restore(&self, context: &mut C);
+}
+
+/// In-memory [`SagaProgress`], which keeps a clone of the context
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Checkpoint<C> {
+    completed: usize,
+    context: C,
+}
+
+impl<C> Checkpoint<C> {
+    /// Create a new checkpoint of `completed` steps, which left `context`.
+    pub fn new(completed: usize, context: C) -> Self {
+        Self { completed, context }
+    }
+
+    /// Number of completed steps which are not compensated
+    pub fn completed(&self) -> usize {
+        self.completed
+    }
+
+    /// Context left by the completed steps
+    pub fn context(&self) -> &C {
+        &self.context
+    }
+}
+
+impl<C: Clone> SagaProgress<C> for Checkpoint<C> {
+    fn completed(&self) -> usize {
+        self.completed
+    }
+
+    fn set_completed(&mut self, completed: usize, context: &C) {
+        self.completed = completed;
+        self.context.clone_from(context);
+    }
+
+    fn restore(&self, context: &mut C) {
+        context.clone_from(&self.context);
+    }
+}
+
+/// Progress of [`run`][Saga::run], which is not recorded
+struct Unrecorded;
+
+impl<C> SagaProgress<C> for Unrecorded {
+    fn completed(&self) -> usize {
+        0
+    }
+
+    fn set_completed(&mut self, _completed: usize, _context: &C) {}
+
+    fn restore(&self, _context: &mut C) {}
+}
+
+/// Report of running a saga
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SagaReport<'a, E> {
+    /// Steps whose action succeeded in this run, in order of running
+    ran: Vec<&'a str>,
+    /// Steps successfully compensated, in order of compensating
+    compensated: Vec<&'a str>,
+    /// Steps whose compensation failed, in order of compensating
+    compensation_errors: Vec<(&'a str, E)>,
+    /// Step whose action failed
+    error: Option<(&'a str, E)>,
+}
+
+impl<'a, E> SagaReport<'a, E> {
+    /// Steps whose action succeeded in this run, in order of running
+    pub fn ran(&self) -> &[&'a str] {
+        &self.ran
+    }
+
+    /// Steps successfully compensated, in order of compensating
+    pub fn compensated(&self) -> &[&'a str] {
+        &self.compensated
+    }
+
+    /// Steps whose compensation failed, together with the errors, in order of compensating
+    pub fn compensation_errors(&self) -> &[(&'a str, E)] {
+        &self.compensation_errors
+    }
+
+    /// Step whose action failed, together with the error
+    pub fn error(&self) -> Option<&(&'a str, E)> {
+        self.error.as_ref()
+    }
+
+    /// Whether all steps succeeded
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Convert to `Ok` if all steps succeeded, otherwise a [`RollbackError`]
+    /// with the error of the failed step and errors of compensations.
+    pub fn into_result(self) -> Result<(), RollbackError<E, E>> {
+        match self.error {
+            None => Ok(()),
+            Some((_, error)) => Err(RollbackError::new(
+                error,
+                self.compensation_errors
+                    .into_iter()
+                    .map(|(_, error)| error)
+                    .collect(),
+            )),
+        }
+    }
+}
+
+/// Running saga, which is the value of a stated scope guard, so that
+/// compensations are run even if an action panics
+struct Run<'r, 'a, C, E, P> {
+    context: &'r mut C,
+    progress: &'r mut P,
+    /// Compensations of completed steps, in order of completion
+    compensations: Vec<(&'a str, Action<'a, C, E>)>,
+    report: SagaReport<'a, E>,
+}
+
+/// Callback of a running saga, which compensates all completed steps in
+/// reverse order unless committed, and returns the report
+struct Compensate;
+
+impl<'a, C, E, P> StatedDrop<Run<'_, 'a, C, E, P>, bool> for Compensate
+where
+    P: SagaProgress<C>,
+{
+    type Output = SagaReport<'a, E>;
+
+    fn call(self, run: Run<'_, 'a, C, E, P>, committed: &bool) -> SagaReport<'a, E> {
+        if *committed {
+            return run.report;
+        }
+        let Run {
+            context,
+            progress,
+            mut compensations,
+            mut report,
+        } = run;
+        // The progress only counts a prefix of steps, so it cannot move past a
+        // step whose compensation failed
+        let mut failed = false;
+        while let Some((name, compensation)) = compensations.pop() {
+            match compensation(context) {
+                Ok(()) => {
+                    report.compensated.push(name);
+                    if !failed {
+                        progress.set_completed(compensations.len(), context);
+                    }
+                }
+                Err(error) => {
+                    report.compensation_errors.push((name, error));
+                    failed = true;
+                }
+            }
+        }
+        report
+    }
+}
+
+impl<'a, C, E> Saga<'a, C, E> {
+    /// Create a new empty saga.
+    pub fn new() -> Self {
+        Self { steps: Vec::new() }
+    }
+
+    /// Add a step named `name`, whose `compensation` is run when a later step fails.
+    pub fn step<A, B>(mut self, name: &'a str, action: A, compensation: B) -> Self
+    where
+        A: FnOnce(&mut C) -> Result<(), E> + 'a,
+        B: FnOnce(&mut C) -> Result<(), E> + 'a,
+    {
+        self.steps.push(Step {
+            name,
+            action: Box::new(action),
+            compensation: Box::new(compensation),
+        });
+        self
+    }
+
+    /// Run all steps in order with `context`. When a step fails, compensations
+    /// of all completed steps are run in reverse order.
+    pub fn run(self, context: &mut C) -> SagaReport<'a, E> {
+        self.run_resumable(context, &mut Unrecorded)
+    }
+
+    /// Run steps with `context`, skipping actions of the first
+    /// [`completed`][SagaProgress::completed] steps of `progress`, and record
+    /// the progress together with the context after every action and
+    /// successful compensation.
+    ///
+    /// If any step is completed, `context` is first
+    /// [`restore`][SagaProgress::restore]d from `progress`, so that the outputs
+    /// of skipped steps are available to later steps and compensations.
+    ///
+    /// When a step fails, compensations of all completed steps are run in
+    /// reverse order, including the skipped ones. Once a compensation fails,
+    /// the progress is no longer decreased, so that the step whose compensation
+    /// failed is still recorded as completed.
+    ///
+    /// ```
+    /// use stated_scope_guard::saga::{Checkpoint, Saga};
+    ///
+    /// let mut progress = Checkpoint::default();
+    /// let report = Saga::new()
+    ///     .step("log dir", |_| Ok(()), |_| Ok(()))
+    ///     .step("user account", |_| Ok(()), |_| Err("cannot delete user account"))
+    ///     .step("user network", |_| Err("cannot create network"), |_| Ok(()))
+    ///     .run_resumable(&mut (), &mut progress);
+    /// assert_eq!(report.compensated(), ["log dir"]);
+    /// assert_eq!(progress.completed(), 2);
+    /// ```
+    pub fn run_resumable<P>(self, context: &mut C, progress: &mut P) -> SagaReport<'a, E>
+    where
+        P: SagaProgress<C>,
+    {
+        let skipped = progress.completed();
+        if skipped > 0 {
+            progress.restore(context);
+        }
+        let mut run = ScopeGuard::with_handler(
+            Run {
+                context,
+                progress,
+                compensations: Vec::new(),
+                report: SagaReport {
+                    ran: Vec::new(),
+                    compensated: Vec::new(),
+                    compensation_errors: Vec::new(),
+                    error: None,
+                },
+            },
+            false,
+            Compensate,
+        );
+        for (index, step) in self.steps.into_iter().enumerate() {
+            if index >= skipped {
+                if let Err(error) = (step.action)(run.context) {
+                    run.report.error = Some((step.name, error));
+                    break;
+                }
+                run.report.ran.push(step.name);
+                let Run {
+                    context, progress, ..
+                } = &mut *run;
+                progress.set_completed(index + 1, context);
+            }
+            run.compensations.push((step.name, step.compensation));
+        }
+        if run.report.error.is_none() {
+            run.set_state(true);
+        }
+        run.finish()
+    }
+}
+
+impl<C, E> Default for Saga<'_, C, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
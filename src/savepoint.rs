@@ -0,0 +1,76 @@
+//! Savepoints of an undo log.
+//!
+//! A transaction recording an undo log, such as a `TxVec` or a `GuardStack`
+//! with the `alloc` feature enabled, can be partially rolled back. A
+//! [`Savepoint`] mutably borrows the transaction, and derefs to it, so that
+//! changes can be made through the savepoint, and nested savepoints can be
+//! created from it.
+//! When the savepoint is dropped or [`rollback`][Savepoint::rollback] is
+//! called, only the changes made after it are undone, unless it is
+//! [`release`][Savepoint::release]d.
+//!
+//! Since a savepoint borrows its transaction, it cannot outlive the
+//! transaction, or be used with another one.
+
+use core::ops::{Deref, DerefMut};
+
+use crate::dismissible::{DismissibleCallback, DismissibleScopeGuard};
+
+/// Log of changes which can be undone back to a previous mark
+pub trait UndoLog {
+    /// Mark of a position in the log, together with anything else needed to
+    /// undo the changes recorded after it
+    type Mark;
+
+    /// Undo all changes recorded after `mark`, in reverse order.
+    fn rollback_to(&mut self, mark: Self::Mark);
+}
+
+/// Savepoint of an undo log, which undoes the changes made after it when
+/// dropped, unless released
+pub struct Savepoint<'t, X: UndoLog + ?Sized> {
+    guard: Inner<'t, X, X::Mark>,
+}
+
+/// Inner [`ScopeGuard`][crate::ScopeGuard] of [`Savepoint`]
+type Inner<'t, X, M> =
+    DismissibleScopeGuard<(&'t mut X, M), DismissibleCallback<fn((&'t mut X, M))>>;
+
+/// Undo the changes made after the savepoint.
+fn rollback_to<X: UndoLog + ?Sized>((log, mark): (&mut X, X::Mark)) {
+    log.rollback_to(mark);
+}
+
+impl<'t, X: UndoLog + ?Sized> Savepoint<'t, X> {
+    /// Create a new savepoint of `log` at `mark`, which shall be the current
+    /// position of `log`.
+    pub fn new(log: &'t mut X, mark: X::Mark) -> Self {
+        Self {
+            guard: DismissibleScopeGuard::new_dismissible((log, mark), rollback_to::<X>),
+        }
+    }
+
+    /// Keep the changes made after the savepoint, which are then part of the
+    /// outer transaction or savepoint.
+    pub fn release(mut self) {
+        self.guard.dismiss();
+    }
+
+    /// Undo the changes made after the savepoint now, which is the same as
+    /// dropping it.
+    pub fn rollback(self) {}
+}
+
+impl<X: UndoLog + ?Sized> Deref for Savepoint<'_, X> {
+    type Target = X;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.guard.0
+    }
+}
+
+impl<X: UndoLog + ?Sized> DerefMut for Savepoint<'_, X> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut *self.guard.0
+    }
+}
@@ -0,0 +1,111 @@
+//! Stated scope guard publishing a shadow copy on commit.
+//!
+//! The inverse of [`RestoreGuard`][crate::restore::RestoreGuard]: [`ShadowGuard`]
+//! borrows the original value, and derefs to a working copy of it. Only when
+//! dropped or finished with a [committed][CommitState::is_committed] state, the
+//! copy is swapped into place. Otherwise, the copy is discarded, and the original
+//! value is never touched, so readers never see a half-updated value.
+//!
+//! ```
+//! use stated_scope_guard::shadow::ShadowGuard;
+//! use stated_scope_guard::Outcome;
+//!
+//! #[derive(Clone, Debug, PartialEq)]
+//! struct Config {
+//!     port: u16,
+//!     host: &'static str,
+//! }
+//!
+//! fn update(config: &mut Config, port: u16, host: &'static str) -> Result<(), ()> {
+//!     let mut shadow = ShadowGuard::new(config, Outcome::Rollback);
+//!     shadow.port = port;
+//!     if host.is_empty() {
+//!         return Err(());
+//!     }
+//!     shadow.host = host;
+//!     shadow.set_state(Outcome::Commit);
+//!     Ok(())
+//! }
+//!
+//! let mut config = Config { port: 80, host: "localhost" };
+//! assert!(update(&mut config, 8080, "").is_err());
+//! assert_eq!(config, Config { port: 80, host: "localhost" });
+//! assert!(update(&mut config, 8080, "example.com").is_ok());
+//! assert_eq!(config, Config { port: 8080, host: "example.com" });
+//! ```
+
+use core::mem;
+use core::ops::{Deref, DerefMut};
+
+use crate::{CommitState, Outcome, ScopeGuard};
+
+/// Stated scope guard working on a copy of a mutably borrowed value, which is
+/// swapped into place when dropped with a committed state
+///
+/// [`Deref`] and [`DerefMut`] target the copy, not the original value.
+pub struct ShadowGuard<'a, T, S = Outcome> {
+    guard: Inner<'a, T, S>,
+}
+
+/// Inner [`ScopeGuard`] of [`ShadowGuard`]
+type Inner<'a, T, S> = ScopeGuard<(&'a mut T, T), S, fn((&'a mut T, T), &S)>;
+
+/// Swap the copy into place if committed, and drop the other one.
+fn publish<T, S: CommitState>((original, mut shadow): (&mut T, T), state: &S) {
+    if state.is_committed() {
+        mem::swap(original, &mut shadow);
+    }
+}
+
+impl<'a, T, S> ShadowGuard<'a, T, S>
+where
+    S: CommitState,
+{
+    /// Create a new shadow guard of `original` with initial `state`, whose
+    /// working copy is a clone of `original`.
+    pub fn new(original: &'a mut T, state: S) -> Self
+    where
+        T: Clone,
+    {
+        let shadow = original.clone();
+        Self::with_shadow(original, shadow, state)
+    }
+
+    /// Create a new shadow guard of `original` with initial `state`, whose
+    /// working copy is `shadow`, for types which cannot be cloned, or whose
+    /// copy can be built more cheaply.
+    pub fn with_shadow(original: &'a mut T, shadow: T, state: S) -> Self {
+        Self {
+            guard: ScopeGuard::new((original, shadow), state, publish::<T, S>),
+        }
+    }
+
+    /// Original value, which is untouched until committed
+    pub fn original(&self) -> &T {
+        &*self.guard.0
+    }
+
+    /// Set current state to `state`
+    pub fn set_state(&mut self, state: S) {
+        self.guard.set_state(state);
+    }
+
+    /// Finish the shadow guard now, swapping the copy into place if committed.
+    pub fn finish(self) {
+        self.guard.finish()
+    }
+}
+
+impl<T, S> Deref for ShadowGuard<'_, T, S> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard.1
+    }
+}
+
+impl<T, S> DerefMut for ShadowGuard<'_, T, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard.1
+    }
+}
@@ -0,0 +1,270 @@
+//! State shared by multiple independent stated scope guards.
+//!
+//! Each [`ScopeGuard`] owns its state, so guards living in different structs or
+//! functions cannot be linked to commit together. Instead, the state of a guard can
+//! be a [`StateHandle`], such as [`SharedStateRef`] borrowing a [`RefCell`],
+//! `SharedState` backed by `Rc` with the `alloc` feature enabled, or
+//! `SyncSharedState` backed by `Arc` and `Mutex` with the `std` feature
+//! enabled, which can be sent to other threads.
+//! Setting the state through the handle decides how every linked guard drops.
+//!
+//! ```
+//! use core::cell::RefCell;
+//! use stated_scope_guard::shared::SharedStateRef;
+//!
+//! let deleted = RefCell::new(Vec::new());
+//! let committed = RefCell::new(false);
+//! let state = SharedStateRef::new(&committed);
+//! {
+//!     let _log_dir = state.guard("log dir", |name, committed| {
+//!         if !*committed { deleted.borrow_mut().push(name) }
+//!     });
+//!     let _user_account = state.guard("user account", |name, committed| {
+//!         if !*committed { deleted.borrow_mut().push(name) }
+//!     });
+//!     state.set(true);
+//! }
+//! assert!(deleted.into_inner().is_empty());
+//! ```
+
+#[cfg(feature = "alloc")]
+use alloc::rc::Rc;
+use core::cell::RefCell;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::{ScopeGuard, StatedDrop};
+
+/// Handle to a state shared by multiple stated scope guards
+pub trait StateHandle {
+    /// Type of the shared state
+    type State;
+
+    /// Take a snapshot of current shared state.
+    ///
+    /// Callbacks are called with the snapshot, so that no lock or borrow of
+    /// the shared state is held while they run. They may set the shared state,
+    /// or drop other guards linked to it.
+    fn snapshot(&self) -> Self::State;
+}
+
+/// Callback of a stated scope guard whose state is a [`StateHandle`], which
+/// calls the inner callback with a snapshot of current shared state
+pub struct Linked<F>(F);
+
+impl<T, H, F> StatedDrop<T, H> for Linked<F>
+where
+    H: StateHandle,
+    F: StatedDrop<T, H::State>,
+{
+    type Output = F::Output;
+
+    fn call(self, value: T, handle: &H) -> F::Output {
+        self.0.call(value, &handle.snapshot())
+    }
+}
+
+/// Stated scope guard whose state is shared through handle `H`
+pub type LinkedScopeGuard<T, H, F> = ScopeGuard<T, H, Linked<F>>;
+
+impl<T, H, F> LinkedScopeGuard<T, H, F>
+where
+    H: StateHandle,
+    F: StatedDrop<T, H::State>,
+{
+    /// Create a new stated scope guard linked to the shared state of `handle`,
+    /// with any implementor of [`StatedDrop`] as callback.
+    pub fn linked(value: T, handle: H, handler: F) -> Self {
+        ScopeGuard::with_handler(value, handle, Linked(handler))
+    }
+}
+
+/// Handle to a shared state borrowing a [`RefCell`], which does not need allocation
+pub struct SharedStateRef<'a, S>(&'a RefCell<S>);
+
+impl<S> Clone for SharedStateRef<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for SharedStateRef<'_, S> {}
+
+impl<'a, S> SharedStateRef<'a, S> {
+    /// Create a new handle to the shared state in `cell`.
+    pub fn new(cell: &'a RefCell<S>) -> Self {
+        Self(cell)
+    }
+
+    /// Set the shared state to `state`
+    pub fn set(&self, state: S) {
+        *self.0.borrow_mut() = state;
+    }
+
+    /// Create a new stated scope guard linked to this shared state.
+    ///
+    /// The `callback` takes `value` and the shared state as parameter.
+    pub fn guard<T, F, R>(&self, value: T, callback: F) -> LinkedScopeGuard<T, Self, F>
+    where
+        S: Clone,
+        F: FnOnce(T, &S) -> R,
+    {
+        LinkedScopeGuard::linked(value, *self, callback)
+    }
+}
+
+impl<S: Clone> StateHandle for SharedStateRef<'_, S> {
+    type State = S;
+
+    fn snapshot(&self) -> S {
+        self.0.borrow().clone()
+    }
+}
+
+/// Handle to a shared state backed by [`Rc`]
+///
+/// Cloning the handle creates a new handle to the same state.
+///
+/// ```
+/// use stated_scope_guard::shared::{LinkedScopeGuard, SharedState};
+///
+/// struct Setup {
+///     log_dir: LinkedScopeGuard<&'static str, SharedState<bool>, fn(&'static str, &bool)>,
+/// }
+///
+/// let state = SharedState::new(false);
+/// let setup = Setup {
+///     log_dir: state.guard("log dir", |_, committed| assert!(*committed)),
+/// };
+/// state.set(true);
+/// drop(setup);
+/// ```
+#[cfg(feature = "alloc")]
+pub struct SharedState<S>(Rc<RefCell<S>>);
+
+#[cfg(feature = "alloc")]
+impl<S> Clone for SharedState<S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S> SharedState<S> {
+    /// Create a new shared state with initial `state`.
+    pub fn new(state: S) -> Self {
+        Self(Rc::new(RefCell::new(state)))
+    }
+
+    /// Set the shared state to `state`
+    pub fn set(&self, state: S) {
+        *self.0.borrow_mut() = state;
+    }
+
+    /// Create a new stated scope guard linked to this shared state.
+    ///
+    /// The `callback` takes `value` and the shared state as parameter.
+    pub fn guard<T, F, R>(&self, value: T, callback: F) -> LinkedScopeGuard<T, Self, F>
+    where
+        S: Clone,
+        F: FnOnce(T, &S) -> R,
+    {
+        LinkedScopeGuard::linked(value, self.clone(), callback)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Clone> StateHandle for SharedState<S> {
+    type State = S;
+
+    fn snapshot(&self) -> S {
+        self.0.borrow().clone()
+    }
+}
+
+/// Handle to a shared state backed by [`Arc`] and [`Mutex`], which is [`Send`]
+/// and [`Sync`] if the state is [`Send`]
+///
+/// Cloning the handle creates a new handle to the same state. A poisoned mutex
+/// is still used, so that linked guards are dropped with the last state set.
+///
+/// ```
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use std::thread;
+/// use stated_scope_guard::shared::SyncSharedState;
+///
+/// #[derive(Debug, Clone, PartialEq)]
+/// enum Decision {
+///     Pending,
+///     Committed,
+/// }
+///
+/// let deleted = AtomicBool::new(false);
+/// let state = SyncSharedState::new(Decision::Pending);
+/// {
+///     let _log_dir = state.guard("log dir", |_, decision| {
+///         if *decision != Decision::Committed {
+///             deleted.store(true, Ordering::Relaxed);
+///         }
+///     });
+///     let handle = state.clone();
+///     thread::spawn(move || handle.set(Decision::Committed)).join().unwrap();
+/// }
+/// assert!(!deleted.load(Ordering::Relaxed));
+///
+/// // Callbacks are called without holding the mutex, so that linked guards
+/// // can be nested, and the state can be set from a callback
+/// let state = SyncSharedState::new(Decision::Pending);
+/// let inner = state.guard("user account", |_, _| {});
+/// drop(state.guard(inner, |inner, _| {
+///     drop(inner);
+///     state.set(Decision::Committed);
+/// }));
+/// ```
+#[cfg(feature = "std")]
+pub struct SyncSharedState<S>(Arc<Mutex<S>>);
+
+#[cfg(feature = "std")]
+impl<S> Clone for SyncSharedState<S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S> SyncSharedState<S> {
+    /// Create a new shared state with initial `state`.
+    pub fn new(state: S) -> Self {
+        Self(Arc::new(Mutex::new(state)))
+    }
+
+    /// Lock the shared state, ignoring poisoning.
+    fn lock(&self) -> MutexGuard<'_, S> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Set the shared state to `state`
+    pub fn set(&self, state: S) {
+        *self.lock() = state;
+    }
+
+    /// Create a new stated scope guard linked to this shared state.
+    ///
+    /// The `callback` takes `value` and the shared state as parameter.
+    pub fn guard<T, F, R>(&self, value: T, callback: F) -> LinkedScopeGuard<T, Self, F>
+    where
+        S: Clone,
+        F: FnOnce(T, &S) -> R,
+    {
+        LinkedScopeGuard::linked(value, self.clone(), callback)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Clone> StateHandle for SyncSharedState<S> {
+    type State = S;
+
+    fn snapshot(&self) -> S {
+        self.lock().clone()
+    }
+}
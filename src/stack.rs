@@ -0,0 +1,294 @@
+//! Stack of stated scope guards sharing a single state.
+//!
+//! When a function sets up many resources, creating one [`ScopeGuard`][crate::ScopeGuard]
+//! for each resource means the state has to be set once for every guard.
+//! [`GuardStack`] holds only one state for all the callbacks pushed onto it,
+//! and calls them in reverse order of registration when dropped.
+//!
+//! ```
+//! use stated_scope_guard::stack::GuardStack;
+//!
+//! # fn create_log_dir() -> Result<&'static str, ()> { Ok("log") }
+//! # fn create_user_account() -> Result<&'static str, ()> { Ok("user") }
+//! # fn create_user_network() -> Result<&'static str, ()> { Ok("network") }
+//! # fn delete(_: &str) {}
+//! #[derive(PartialEq)]
+//! enum State {
+//!     SomethingWrong,
+//!     AllThingsGoRight,
+//! }
+//!
+//! fn setup() -> Result<(), ()> {
+//!     let mut stack = GuardStack::new(State::SomethingWrong);
+//!     stack.push(create_log_dir()?, |log_dir, state| {
+//!         if *state == State::SomethingWrong {
+//!             delete(log_dir);
+//!         }
+//!     });
+//!     stack.push(create_user_account()?, |user_account, state| {
+//!         if *state == State::SomethingWrong {
+//!             delete(user_account);
+//!         }
+//!     });
+//!     stack.push(create_user_network()?, |network, state| {
+//!         if *state == State::SomethingWrong {
+//!             delete(network);
+//!         }
+//!     });
+//!     // One state change for all resources
+//!     stack.set_state(State::AllThingsGoRight);
+//!     Ok(())
+//! }
+//! # setup().unwrap();
+//! ```
+//!
+//! Like [`ScopeGuard`][crate::ScopeGuard], callbacks may return a value of type `R`, which
+//! can be collected by [`finish`][GuardStack::finish]. When callbacks return
+//! `Result<(), R>`, a failed setup can be turned into a [`RollbackError`] by
+//! [`fail`][GuardStack::fail], which keeps both the primary error and every
+//! error of the rollback:
+//!
+//! ```
+//! use stated_scope_guard::stack::GuardStack;
+//! use stated_scope_guard::rollback::RollbackError;
+//!
+//! fn setup() -> Result<(), RollbackError<&'static str, &'static str>> {
+//!     let mut stack = GuardStack::new(false);
+//!     stack.push("log dir", |_, committed| {
+//!         if *committed { Ok(()) } else { Err("cannot delete log dir") }
+//!     });
+//!     stack.push("user account", |_, _| Ok(()));
+//!     let step3: Result<(), &str> = Err("cannot create network");
+//!     if let Err(error) = step3 {
+//!         return Err(stack.fail(error));
+//!     }
+//!     stack.set_state(true);
+//!     Ok(())
+//! }
+//!
+//! let error = setup().unwrap_err();
+//! assert_eq!(*error.error(), "cannot create network");
+//! assert_eq!(error.rollback_errors(), ["cannot delete log dir"]);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::iter;
+
+use crate::rollback::RollbackError;
+use crate::savepoint::{Savepoint, UndoLog};
+
+/// Type-erased callback, which has already captured its value
+type Callback<'a, S, R> = Box<dyn FnOnce(&S) -> R + 'a>;
+
+/// Stack of callbacks sharing a single state
+///
+/// The callbacks may return a value of type `R`, which can be collected by
+/// [`finish`][GuardStack::finish], and is discarded when the stack is dropped.
+pub struct GuardStack<'a, S, R = ()> {
+    /// State shared by all callbacks
+    state: S,
+    /// Registered callbacks, in order of registration
+    callbacks: Vec<Callback<'a, S, R>>,
+}
+
+impl<'a, S, R> GuardStack<'a, S, R> {
+    /// Create a new empty guard stack with initial `state`.
+    pub fn new(state: S) -> Self {
+        Self {
+            state,
+            callbacks: Vec::new(),
+        }
+    }
+
+    /// Push a new guarded `value` onto the stack.
+    ///
+    /// The `callback` takes `value` and the state of the stack as parameter,
+    /// just like the callback of [`ScopeGuard`][crate::ScopeGuard], and will be called
+    /// when the stack is dropped, before all callbacks pushed earlier.
+    pub fn push<T, F>(&mut self, value: T, callback: F)
+    where
+        T: 'a,
+        F: FnOnce(T, &S) -> R + 'a,
+    {
+        self.callbacks
+            .push(Box::new(move |state: &S| callback(value, state)));
+    }
+
+    /// Set state of the whole stack to `state`
+    pub fn set_state(&mut self, state: S) {
+        self.state = state;
+    }
+
+    /// Current state of the stack
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Number of registered callbacks
+    pub fn len(&self) -> usize {
+        self.callbacks.len()
+    }
+
+    /// Whether there is no registered callback
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+
+    /// Run `body` with a new guard stack, whose state is decided by the
+    /// outcome of `body`.
+    ///
+    /// The stack is created with `rollback` state, and its state is set to
+    /// `commit` only if `body` returns `Ok`. As a result, a panic in `body` will
+    /// drop the stack with `rollback` state as long as `body` does not set the
+    /// state itself.
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use stated_scope_guard::stack::GuardStack;
+    ///
+    /// let deleted = RefCell::new(Vec::new());
+    /// let result: Result<(), &str> = GuardStack::guarded(false, true, |stack| {
+    ///     stack.push("log dir", |name, committed| {
+    ///         if !*committed { deleted.borrow_mut().push(name) }
+    ///     });
+    ///     stack.push("user account", |name, committed| {
+    ///         if !*committed { deleted.borrow_mut().push(name) }
+    ///     });
+    ///     Err("cannot create network")
+    /// });
+    /// assert!(result.is_err());
+    /// assert_eq!(deleted.into_inner(), ["user account", "log dir"]);
+    /// ```
+    pub fn guarded<B, T, E>(rollback: S, commit: S, body: B) -> Result<T, E>
+    where
+        B: FnOnce(&mut Self) -> Result<T, E>,
+    {
+        let mut stack = Self::new(rollback);
+        let result = body(&mut stack)?;
+        stack.set_state(commit);
+        Ok(result)
+    }
+
+    /// Create a savepoint, so that an optional sub-step can be undone without
+    /// the outer work.
+    ///
+    /// Callbacks can be pushed through the savepoint. When the savepoint is
+    /// dropped or [`rollback`][Savepoint::rollback] is called, only the
+    /// callbacks pushed after it are called, in reverse order of registration,
+    /// with `rollback` as the state instead of the state of the stack, so that
+    /// the sub-step is undone even if the stack has been set to a committed
+    /// state. Earlier callbacks are left armed. When the savepoint is
+    /// [`release`][Savepoint::release]d, its callbacks are kept on the stack.
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use stated_scope_guard::stack::GuardStack;
+    ///
+    /// let deleted = RefCell::new(Vec::new());
+    /// let delete = |name, committed: &bool| {
+    ///     if !*committed { deleted.borrow_mut().push(name) }
+    /// };
+    /// let mut stack = GuardStack::new(false);
+    /// stack.push("log dir", delete);
+    /// {
+    ///     let mut savepoint = stack.savepoint(false);
+    ///     savepoint.push("cache dir", delete);
+    ///     savepoint.push("cache index", delete);
+    ///     // The outer work is done, but the optional sub-step fails
+    ///     savepoint.set_state(true);
+    ///     savepoint.rollback();
+    /// }
+    /// assert_eq!(*deleted.borrow(), ["cache index", "cache dir"]);
+    /// drop(stack);
+    /// assert_eq!(deleted.into_inner(), ["cache index", "cache dir"]);
+    /// ```
+    pub fn savepoint(&mut self, rollback: S) -> Savepoint<'_, Self> {
+        let mark = self.callbacks.len();
+        Savepoint::new(self, (mark, rollback))
+    }
+
+    /// Finish the stack now, calling all callbacks with current state as
+    /// parameter in reverse order of registration, and return what they return
+    /// in the same order.
+    ///
+    /// If a callback panics, the remaining callbacks are still called while
+    /// unwinding.
+    pub fn finish(mut self) -> Vec<R> {
+        let mut drain = Drain {
+            callbacks: &mut self.callbacks,
+            mark: 0,
+            state: &self.state,
+        };
+        iter::from_fn(|| drain.call_next()).collect()
+    }
+}
+
+impl<S, R> GuardStack<'_, S, Result<(), R>> {
+    /// Roll back because of `error`, calling all callbacks with current state
+    /// as parameter in reverse order of registration, and collect every error
+    /// of the rollback together with `error` into a [`RollbackError`].
+    pub fn fail<E>(self, error: E) -> RollbackError<E, R> {
+        let rollback_errors = self.finish().into_iter().filter_map(Result::err).collect();
+        RollbackError::new(error, rollback_errors)
+    }
+}
+
+impl<S, R> UndoLog for GuardStack<'_, S, R> {
+    /// Number of registered callbacks, and the state to call the callbacks
+    /// registered after them with
+    type Mark = (usize, S);
+
+    /// Call the callbacks registered after the mark with the state of the
+    /// mark as parameter, in reverse order of registration.
+    fn rollback_to(&mut self, (mark, rollback): (usize, S)) {
+        let mut drain = Drain {
+            callbacks: &mut self.callbacks,
+            mark,
+            state: &rollback,
+        };
+        while drain.call_next().is_some() {}
+    }
+}
+
+impl<S, R> Drop for GuardStack<'_, S, R> {
+    /// When dropping, all callbacks will be called with current state as
+    /// parameter, in reverse order of registration. If a callback panics, the
+    /// remaining callbacks are still called while unwinding.
+    fn drop(&mut self) {
+        let mut drain = Drain {
+            callbacks: &mut self.callbacks,
+            mark: 0,
+            state: &self.state,
+        };
+        while drain.call_next().is_some() {}
+    }
+}
+
+/// Callbacks registered after `mark`, which are called with `state` in reverse
+/// order of registration
+///
+/// When dropped, all remaining callbacks are called, so that they still run
+/// while unwinding from a panicking callback.
+struct Drain<'v, 'a, S, R> {
+    callbacks: &'v mut Vec<Callback<'a, S, R>>,
+    mark: usize,
+    state: &'v S,
+}
+
+impl<S, R> Drain<'_, '_, S, R> {
+    /// Call the last remaining callback, and return what it returns.
+    fn call_next(&mut self) -> Option<R> {
+        if self.callbacks.len() > self.mark {
+            self.callbacks.pop().map(|callback| callback(self.state))
+        } else {
+            None
+        }
+    }
+}
+
+impl<S, R> Drop for Drain<'_, '_, S, R> {
+    fn drop(&mut self) {
+        while self.call_next().is_some() {}
+    }
+}
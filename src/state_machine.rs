@@ -0,0 +1,150 @@
+//! Runtime-validated state transitions.
+//!
+//! When states are chosen at runtime, they cannot be modeled as typestates
+//! (see [`typestate`][crate::typestate]). Instead, the state type can implement
+//! [`StateMachine`] to declare allowed transitions, and use
+//! [`try_set_state`][ScopeGuard::try_set_state] to reject illegal ones.
+//! [`StateMachine::on_transition`] is called at every accepted transition, which
+//! is useful for side effects such as journaling or metrics.
+//!
+//! Plain [`set_state`][ScopeGuard::set_state] is available for every state type,
+//! and bypasses the state machine: neither
+//! [`can_transition`][StateMachine::can_transition] nor
+//! [`on_transition`][StateMachine::on_transition] is called. To have every state
+//! change checked and hooked, use [`try_set_state`][ScopeGuard::try_set_state],
+//! or [`set_state_checked`][ScopeGuard::set_state_checked], which asserts the
+//! transition in debug builds only.
+//!
+//! ```
+//! use stated_scope_guard::ScopeGuard;
+//! use stated_scope_guard::state_machine::StateMachine;
+//!
+//! #[derive(Debug, PartialEq)]
+//! enum State {
+//!     Created,
+//!     Committed,
+//!     RolledBack,
+//! }
+//!
+//! impl StateMachine for State {
+//!     fn can_transition(&self, to: &Self) -> bool {
+//!         matches!(
+//!             (self, to),
+//!             (State::Created, State::Committed) | (State::Created, State::RolledBack)
+//!         )
+//!     }
+//! }
+//!
+//! let mut guard = ScopeGuard::new((), State::Created, |_, _| {});
+//! guard.try_set_state(State::RolledBack).unwrap();
+//! let error = guard.try_set_state(State::Committed).unwrap_err();
+//! assert_eq!(error.into_inner(), State::Committed);
+//! ```
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+use crate::{ScopeGuard, StatedDrop};
+
+/// State type with declared transitions
+pub trait StateMachine {
+    /// Whether transition from current state to `to` is allowed.
+    fn can_transition(&self, to: &Self) -> bool;
+
+    /// Hook called at every accepted transition from current state to `to`,
+    /// before the state is changed.
+    ///
+    /// It does nothing by default.
+    fn on_transition(&self, to: &Self) {
+        let _ = to;
+    }
+}
+
+/// Error of an illegal state transition, which holds the rejected state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IllegalTransition<S> {
+    /// The rejected state
+    to: S,
+}
+
+impl<S> IllegalTransition<S> {
+    /// Take the rejected state out.
+    pub fn into_inner(self) -> S {
+        self.to
+    }
+}
+
+impl<S> fmt::Display for IllegalTransition<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "illegal state transition to {:?}", self.to)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S> Error for IllegalTransition<S> where S: fmt::Debug {}
+
+impl<T, S, F> ScopeGuard<T, S, F>
+where
+    F: StatedDrop<T, S>,
+    S: StateMachine,
+{
+    /// Set current state to `state` if the transition is allowed, otherwise
+    /// return an error holding `state`, and current state is not changed.
+    pub fn try_set_state(&mut self, state: S) -> Result<(), IllegalTransition<S>> {
+        if !self.state.can_transition(&state) {
+            return Err(IllegalTransition { to: state });
+        }
+        self.state.on_transition(&state);
+        self.state = state;
+        Ok(())
+    }
+
+    /// Set current state to `state`, asserting the transition is allowed in
+    /// debug builds.
+    ///
+    /// In release builds, the transition is not checked, and
+    /// [`on_transition`][StateMachine::on_transition] is still called.
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use stated_scope_guard::ScopeGuard;
+    /// use stated_scope_guard::state_machine::StateMachine;
+    ///
+    /// thread_local! {
+    ///     static JOURNAL: RefCell<Vec<(u8, u8)>> = RefCell::new(Vec::new());
+    /// }
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct Step(u8);
+    ///
+    /// impl StateMachine for Step {
+    ///     fn can_transition(&self, to: &Self) -> bool {
+    ///         to.0 == self.0 + 1
+    ///     }
+    ///
+    ///     fn on_transition(&self, to: &Self) {
+    ///         JOURNAL.with(|journal| journal.borrow_mut().push((self.0, to.0)));
+    ///     }
+    /// }
+    ///
+    /// let mut guard = ScopeGuard::new((), Step(0), |_, _| {});
+    /// guard.set_state_checked(Step(1));
+    /// guard.try_set_state(Step(2)).unwrap();
+    /// // Rejected, so the hook is not called
+    /// assert!(guard.try_set_state(Step(4)).is_err());
+    /// guard.set_state_checked(Step(3));
+    /// JOURNAL.with(|journal| assert_eq!(*journal.borrow(), [(0, 1), (1, 2), (2, 3)]));
+    /// ```
+    pub fn set_state_checked(&mut self, state: S) {
+        debug_assert!(
+            self.state.can_transition(&state),
+            "illegal state transition"
+        );
+        self.state.on_transition(&state);
+        self.state = state;
+    }
+}
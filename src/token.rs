@@ -0,0 +1,181 @@
+//! Linear commit token.
+//!
+//! [`dismiss`][crate::dismissible::DismissibleScopeGuard::dismiss] needs a `&mut`
+//! borrow of the guard, so the right to commit cannot be handed to other code
+//! without the guard itself. [`new_with_token`] creates a guard together with a
+//! non-cloneable [`CommitToken`], which can be moved to whatever code is
+//! responsible for the decision, even on another thread. Consuming the token by
+//! [`commit`][CommitToken::commit] commits the guard, while dropping the token
+//! unconsumed aborts it, and the callback will be called.
+//!
+//! If the guard is dropped while its token is neither consumed nor dropped, the
+//! guard aborts. This happens when the token is leaked by
+//! [`mem::forget`][core::mem::forget], but also when the token is still alive,
+//! and the guard cannot tell them apart. Such a drop is reported to the
+//! standard error in debug builds with the `std` feature enabled, and can be
+//! reported by another hook passed to [`new_with_token_hook`].
+//!
+//! ```
+//! use std::sync::atomic::{AtomicBool, Ordering};
+//! use std::thread;
+//! use stated_scope_guard::token::{new_with_token, CommitToken};
+//!
+//! fn decide(token: CommitToken, success: bool) {
+//!     if success {
+//!         token.commit();
+//!     }
+//! }
+//!
+//! let deleted = AtomicBool::new(false);
+//! {
+//!     let (_guard, token) = new_with_token("log dir", |_| deleted.store(true, Ordering::Relaxed));
+//!     thread::spawn(move || decide(token, false)).join().unwrap();
+//! }
+//! assert!(deleted.load(Ordering::Relaxed));
+//! ```
+
+use crate::atomic::{AtomicRepr, StateController};
+use crate::shared::LinkedScopeGuard;
+use crate::StatedDrop;
+
+/// Decision made by a [`CommitToken`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Decision {
+    /// The token is neither consumed nor dropped
+    Pending,
+    /// The token is consumed by [`commit`][CommitToken::commit]
+    Committed,
+    /// The token is dropped unconsumed
+    Aborted,
+}
+
+impl AtomicRepr for Decision {
+    fn into_repr(self) -> u8 {
+        self as u8
+    }
+
+    fn from_repr(repr: u8) -> Self {
+        match repr {
+            0 => Decision::Pending,
+            1 => Decision::Committed,
+            _ => Decision::Aborted,
+        }
+    }
+}
+
+/// Non-cloneable token holding the right to commit a guard created by [`new_with_token`]
+#[must_use = "dropping the token aborts the guard"]
+pub struct CommitToken {
+    decision: StateController<Decision>,
+}
+
+impl CommitToken {
+    /// Commit the guard, so that its callback will not be called.
+    pub fn commit(self) {
+        self.decision.set(Decision::Committed);
+    }
+
+    /// Abort the guard explicitly, which is the same as dropping the token.
+    pub fn abort(self) {}
+}
+
+impl Drop for CommitToken {
+    /// When dropping without [`commit`][CommitToken::commit], the guard is aborted.
+    fn drop(&mut self) {
+        if self.decision.get() == Decision::Pending {
+            self.decision.set(Decision::Aborted);
+        }
+    }
+}
+
+/// Callback of a guard created by [`new_with_token`], which calls the inner
+/// callback unless committed, and then calls the hook if the token is neither
+/// consumed nor dropped
+pub struct TokenCallback<F, H = fn()> {
+    callback: F,
+    on_pending: H,
+}
+
+impl<T, F, H> StatedDrop<T, Decision> for TokenCallback<F, H>
+where
+    F: FnOnce(T),
+    H: FnOnce(),
+{
+    type Output = ();
+
+    fn call(self, value: T, decision: &Decision) {
+        match decision {
+            Decision::Committed => {}
+            Decision::Aborted => (self.callback)(value),
+            Decision::Pending => {
+                // The guard is aborted even if the hook panics
+                (self.callback)(value);
+                (self.on_pending)();
+            }
+        }
+    }
+}
+
+/// Stated scope guard created by [`new_with_token`]
+pub type TokenScopeGuard<T, F, H = fn()> =
+    LinkedScopeGuard<T, StateController<Decision>, TokenCallback<F, H>>;
+
+/// Create a new stated scope guard together with its [`CommitToken`]. The
+/// `callback` will be called when the guard is dropped, unless the token is
+/// consumed by [`commit`][CommitToken::commit] before.
+pub fn new_with_token<T, F: FnOnce(T)>(
+    value: T,
+    callback: F,
+) -> (TokenScopeGuard<T, F>, CommitToken) {
+    new_with_token_hook(value, callback, report_pending as fn())
+}
+
+/// Default hook of [`new_with_token`], which reports the pending token to the
+/// standard error in debug builds with the `std` feature enabled
+fn report_pending() {
+    #[cfg(all(feature = "std", debug_assertions))]
+    std::eprintln!("scope guard is dropped while its commit token is neither consumed nor dropped");
+}
+
+/// Create a new stated scope guard together with its [`CommitToken`], just like
+/// [`new_with_token`]. If the guard is dropped while the token is neither
+/// consumed nor dropped, `on_pending` is called after the callback instead of
+/// the default hook.
+///
+/// ```
+/// use std::cell::Cell;
+/// use stated_scope_guard::token::new_with_token_hook;
+///
+/// let aborted = Cell::new(false);
+/// let reported = Cell::new(false);
+/// let (guard, token) = new_with_token_hook(
+///     "log dir",
+///     |_| aborted.set(true),
+///     || reported.set(aborted.get()),
+/// );
+/// std::mem::forget(token);
+/// drop(guard);
+/// // The hook is called after the guard is aborted
+/// assert!(reported.get());
+/// ```
+pub fn new_with_token_hook<T, F, H>(
+    value: T,
+    callback: F,
+    on_pending: H,
+) -> (TokenScopeGuard<T, F, H>, CommitToken)
+where
+    F: FnOnce(T),
+    H: FnOnce(),
+{
+    let decision = StateController::new(Decision::Pending);
+    let guard = LinkedScopeGuard::linked(
+        value,
+        decision.clone(),
+        TokenCallback {
+            callback,
+            on_pending,
+        },
+    );
+    (guard, CommitToken { decision })
+}
@@ -0,0 +1,283 @@
+//! Two-phase commit across multiple participants.
+//!
+//! When changes span several subsystems, such as files, an embedded database
+//! and an in-process cache, they must all commit or all abort. [`TwoPhaseCommit`]
+//! coordinates [`Participant`]s: in the first phase every participant is asked to
+//! [`prepare`][Participant::prepare], and only if all of them succeed, the
+//! second phase [`commit`][Participant::commit]s every participant.
+//!
+//! Prepared participants are held by a stated scope guard, so that
+//! [`abort`][Participant::abort] is called on every prepared participant when
+//! any preparation fails or panics, or when [`Prepared`] is dropped without
+//! committing. The outcome of every participant is reported by a
+//! [`TwoPhaseReport`].
+//!
+//! ```
+//! use stated_scope_guard::two_phase::{Participant, ParticipantOutcome, TwoPhaseCommit};
+//!
+//! #[derive(Default)]
+//! struct Store {
+//!     value: u32,
+//!     staged: Option<u32>,
+//!     fail_prepare: bool,
+//! }
+//!
+//! impl Participant for Store {
+//!     type Error = &'static str;
+//!
+//!     fn prepare(&mut self) -> Result<(), &'static str> {
+//!         if self.fail_prepare { Err("disk full") } else { Ok(()) }
+//!     }
+//!
+//!     fn commit(&mut self) -> Result<(), &'static str> {
+//!         self.value = self.staged.take().unwrap();
+//!         Ok(())
+//!     }
+//!
+//!     fn abort(&mut self) -> Result<(), &'static str> {
+//!         self.staged = None;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut cache = Store { staged: Some(1), ..Store::default() };
+//! let mut database = Store { staged: Some(1), fail_prepare: true, ..Store::default() };
+//! let report = TwoPhaseCommit::new()
+//!     .participant("cache", &mut cache)
+//!     .participant("database", &mut database)
+//!     .run();
+//! assert!(!report.is_committed());
+//! assert_eq!(
+//!     report.outcomes(),
+//!     [
+//!         ("cache", ParticipantOutcome::Aborted),
+//!         ("database", ParticipantOutcome::PrepareFailed("disk full")),
+//!     ]
+//! );
+//! assert_eq!(cache.staged, None);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::{ScopeGuard, StatedDrop};
+
+/// Participant of a two-phase commit
+pub trait Participant {
+    /// Error of each phase
+    type Error;
+
+    /// Prepare to commit, after which the participant shall be able to either
+    /// commit or abort.
+    fn prepare(&mut self) -> Result<(), Self::Error>;
+
+    /// Commit the prepared changes.
+    fn commit(&mut self) -> Result<(), Self::Error>;
+
+    /// Abort the prepared changes.
+    fn abort(&mut self) -> Result<(), Self::Error>;
+}
+
+impl<P: Participant + ?Sized> Participant for &mut P {
+    type Error = P::Error;
+
+    fn prepare(&mut self) -> Result<(), Self::Error> {
+        (**self).prepare()
+    }
+
+    fn commit(&mut self) -> Result<(), Self::Error> {
+        (**self).commit()
+    }
+
+    fn abort(&mut self) -> Result<(), Self::Error> {
+        (**self).abort()
+    }
+}
+
+/// Outcome of a participant in a two-phase commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParticipantOutcome<E> {
+    /// The participant is not prepared, since an earlier participant failed to prepare
+    NotPrepared,
+    /// The participant failed to prepare
+    PrepareFailed(E),
+    /// The participant is committed
+    Committed,
+    /// The participant failed to commit
+    CommitFailed(E),
+    /// The participant is aborted
+    Aborted,
+    /// The participant failed to abort
+    AbortFailed(E),
+}
+
+/// Report of a two-phase commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwoPhaseReport<'a, E> {
+    /// Outcome of every participant, in order of registration
+    outcomes: Vec<(&'a str, ParticipantOutcome<E>)>,
+    /// Whether the decision is to commit
+    committed: bool,
+}
+
+impl<'a, E> TwoPhaseReport<'a, E> {
+    /// Outcome of every participant, in order of registration
+    pub fn outcomes(&self) -> &[(&'a str, ParticipantOutcome<E>)] {
+        &self.outcomes
+    }
+
+    /// Whether the decision is to commit, i.e., all participants are prepared,
+    /// even if some of them failed to commit
+    pub fn is_committed(&self) -> bool {
+        self.committed
+    }
+
+    /// Whether all participants are committed successfully
+    pub fn is_success(&self) -> bool {
+        self.committed
+            && self
+                .outcomes
+                .iter()
+                .all(|(_, outcome)| matches!(outcome, ParticipantOutcome::Committed))
+    }
+}
+
+/// Registered participant
+struct Entry<'a, E> {
+    name: &'a str,
+    participant: Box<dyn Participant<Error = E> + 'a>,
+    outcome: ParticipantOutcome<E>,
+}
+
+/// Coordinator of a two-phase commit
+pub struct TwoPhaseCommit<'a, E> {
+    entries: Vec<Entry<'a, E>>,
+}
+
+impl<'a, E> TwoPhaseCommit<'a, E> {
+    /// Create a new coordinator without participants.
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add a participant named `name`, which is prepared and committed after
+    /// all participants added before.
+    pub fn participant<P>(mut self, name: &'a str, participant: P) -> Self
+    where
+        P: Participant<Error = E> + 'a,
+    {
+        self.entries.push(Entry {
+            name,
+            participant: Box::new(participant),
+            outcome: ParticipantOutcome::NotPrepared,
+        });
+        self
+    }
+
+    /// Prepare all participants in order of registration.
+    ///
+    /// If all participants are prepared, they are held by [`Prepared`] to be
+    /// committed. Otherwise, every prepared participant is aborted in reverse
+    /// order, and the report is returned as error.
+    pub fn prepare(self) -> Result<Prepared<'a, E>, TwoPhaseReport<'a, E>> {
+        let mut votes = ScopeGuard::with_handler(
+            Votes {
+                entries: self.entries,
+                prepared: 0,
+            },
+            false,
+            Resolve,
+        );
+        while votes.prepared < votes.entries.len() {
+            let index = votes.prepared;
+            let entry = &mut votes.entries[index];
+            if let Err(error) = entry.participant.prepare() {
+                entry.outcome = ParticipantOutcome::PrepareFailed(error);
+                return Err(votes.finish());
+            }
+            votes.prepared += 1;
+        }
+        Ok(Prepared { votes })
+    }
+
+    /// Prepare all participants, and commit them if all of them are prepared.
+    pub fn run(self) -> TwoPhaseReport<'a, E> {
+        match self.prepare() {
+            Ok(prepared) => prepared.commit(),
+            Err(report) => report,
+        }
+    }
+}
+
+impl<E> Default for TwoPhaseCommit<'_, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Participants of a two-phase commit, where the first `prepared` ones are prepared
+struct Votes<'a, E> {
+    entries: Vec<Entry<'a, E>>,
+    prepared: usize,
+}
+
+/// Callback of prepared participants, which commits them in order of
+/// registration if committed, otherwise aborts them in reverse order, and
+/// returns the report
+struct Resolve;
+
+impl<'a, E> StatedDrop<Votes<'a, E>, bool> for Resolve {
+    type Output = TwoPhaseReport<'a, E>;
+
+    fn call(self, votes: Votes<'a, E>, committed: &bool) -> TwoPhaseReport<'a, E> {
+        let Votes {
+            mut entries,
+            prepared,
+        } = votes;
+        let prepared = &mut entries[..prepared];
+        if *committed {
+            for entry in prepared {
+                entry.outcome = match entry.participant.commit() {
+                    Ok(()) => ParticipantOutcome::Committed,
+                    Err(error) => ParticipantOutcome::CommitFailed(error),
+                };
+            }
+        } else {
+            for entry in prepared.iter_mut().rev() {
+                entry.outcome = match entry.participant.abort() {
+                    Ok(()) => ParticipantOutcome::Aborted,
+                    Err(error) => ParticipantOutcome::AbortFailed(error),
+                };
+            }
+        }
+        TwoPhaseReport {
+            outcomes: entries
+                .into_iter()
+                .map(|entry| (entry.name, entry.outcome))
+                .collect(),
+            committed: *committed,
+        }
+    }
+}
+
+/// Prepared participants of a two-phase commit, which are aborted in reverse
+/// order when dropped without [`commit`][Prepared::commit]
+pub struct Prepared<'a, E> {
+    votes: ScopeGuard<Votes<'a, E>, bool, Resolve>,
+}
+
+impl<'a, E> Prepared<'a, E> {
+    /// Commit all participants in order of registration.
+    pub fn commit(mut self) -> TwoPhaseReport<'a, E> {
+        self.votes.set_state(true);
+        self.votes.finish()
+    }
+
+    /// Abort all participants in reverse order of registration, which is the
+    /// same as dropping, but returns the report.
+    pub fn abort(self) -> TwoPhaseReport<'a, E> {
+        self.votes.finish()
+    }
+}
@@ -0,0 +1,571 @@
+//! Transactional collections recording an undo log.
+//!
+//! Rolling back changes of a collection by a [`ScopeGuard`] closure needs to
+//! clone the whole collection at first. [`Tx`] mutably borrows a collection,
+//! and changes it through operations such as push, insert, remove, swap and
+//! truncate, each of which records an entry in an undo log. When dropped or
+//! finished with a state which is not [committed][CommitState::is_committed],
+//! the undo log is replayed in reverse order. The cost is O(changes) rather than
+//! O(size).
+//!
+//! The collection can be read through [`Deref`], while it can only be changed
+//! through [`Tx`], so that every change is recorded.
+//!
+//! ```
+//! use stated_scope_guard::tx::TxVec;
+//! use stated_scope_guard::Outcome;
+//!
+//! let mut queue = vec![1, 2, 3];
+//! {
+//!     let mut tx = TxVec::new(&mut queue, Outcome::Rollback);
+//!     tx.push(4);
+//!     tx.swap(0, 3);
+//!     tx.remove(1);
+//!     assert_eq!(*tx, [4, 3, 1]);
+//! }
+//! assert_eq!(queue, [1, 2, 3]);
+//! ```
+//!
+//! A [`Savepoint`] undoes only the changes made after it:
+//!
+//! ```
+//! use std::collections::BTreeMap;
+//! use stated_scope_guard::tx::TxBTreeMap;
+//! use stated_scope_guard::Outcome;
+//!
+//! let mut users = BTreeMap::from([("alice", 1)]);
+//! let mut tx = TxBTreeMap::new(&mut users, Outcome::Rollback);
+//! tx.insert("bob", 2);
+//! {
+//!     let mut savepoint = tx.savepoint();
+//!     savepoint.insert("alice", 3);
+//!     savepoint.remove(&"bob");
+//!     // Dropped, so the changes above are undone
+//! }
+//! tx.set_state(Outcome::Commit);
+//! drop(tx);
+//! assert_eq!(users, BTreeMap::from([("alice", 1), ("bob", 2)]));
+//! ```
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use crate::savepoint::{Savepoint, UndoLog};
+use crate::{CommitState, Outcome, ScopeGuard};
+
+/// Collection whose changes can be undone by entries of an undo log
+///
+/// It is implemented for [`Vec`], [`VecDeque`], [`BTreeMap`], and `HashMap`
+/// with the `std` feature enabled.
+pub trait Undo {
+    /// Entry of the undo log, which records how to undo a change
+    type Entry;
+
+    /// Undo a change recorded by `entry`.
+    fn undo(&mut self, entry: Self::Entry);
+}
+
+/// Transactional collection recording an undo log, which is replayed in
+/// reverse order when dropped with a state which is not committed
+pub struct Tx<'a, C: Undo, S = Outcome> {
+    guard: Inner<'a, C, S>,
+}
+
+/// Collection together with its undo log
+struct Log<'a, C: Undo> {
+    collection: &'a mut C,
+    entries: Vec<C::Entry>,
+}
+
+impl<C: Undo> Log<'_, C> {
+    /// Record `entry`, and return a reference to it.
+    fn push(&mut self, entry: C::Entry) -> &C::Entry {
+        let index = self.entries.len();
+        self.entries.push(entry);
+        &self.entries[index]
+    }
+
+    /// Record `entry`, and return a reference to the element kept in it.
+    fn push_kept(&mut self, entry: C::Entry) -> &<C::Entry as Kept>::Element
+    where
+        C::Entry: Kept,
+    {
+        match self.push(entry).kept() {
+            Some(element) => element,
+            None => unreachable!("entry does not keep an element"),
+        }
+    }
+
+    /// Undo all changes recorded after `mark`, in reverse order.
+    fn rollback_to(&mut self, mark: usize) {
+        while self.entries.len() > mark {
+            match self.entries.pop() {
+                Some(entry) => self.collection.undo(entry),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Undo log entry which may keep an element removed from the collection
+trait Kept {
+    /// Type of the element
+    type Element;
+
+    /// Element kept in the entry
+    fn kept(&self) -> Option<&Self::Element>;
+}
+
+/// Inner [`ScopeGuard`] of [`Tx`]
+type Inner<'a, C, S> = ScopeGuard<Log<'a, C>, S, fn(Log<'a, C>, &S)>;
+
+/// Undo all changes unless committed.
+fn rollback<C: Undo, S: CommitState>(mut log: Log<'_, C>, state: &S) {
+    if !state.is_committed() {
+        log.rollback_to(0);
+    }
+}
+
+impl<'a, C: Undo, S: CommitState> Tx<'a, C, S> {
+    /// Create a new transaction on `collection` with initial `state`.
+    pub fn new(collection: &'a mut C, state: S) -> Self {
+        Self {
+            guard: ScopeGuard::new(
+                Log {
+                    collection,
+                    entries: Vec::new(),
+                },
+                state,
+                rollback::<C, S>,
+            ),
+        }
+    }
+
+    /// Set current state to `state`
+    pub fn set_state(&mut self, state: S) {
+        self.guard.set_state(state);
+    }
+
+    /// Finish the transaction now, undoing all changes unless committed.
+    pub fn finish(self) {
+        self.guard.finish()
+    }
+
+    /// Create a savepoint, which undoes the changes made after it when dropped,
+    /// unless released.
+    pub fn savepoint(&mut self) -> Savepoint<'_, Self> {
+        let mark = self.guard.entries.len();
+        Savepoint::new(self, mark)
+    }
+
+    /// Change the collection by `change`, and record the returned entry.
+    fn record<R>(&mut self, change: impl FnOnce(&mut C) -> (C::Entry, R)) -> R {
+        let log = &mut *self.guard;
+        let (entry, output) = change(log.collection);
+        log.push(entry);
+        output
+    }
+}
+
+impl<C: Undo, S> UndoLog for Tx<'_, C, S> {
+    /// Number of entries in the undo log
+    type Mark = usize;
+
+    fn rollback_to(&mut self, mark: usize) {
+        self.guard.rollback_to(mark);
+    }
+}
+
+impl<C: Undo, S> Deref for Tx<'_, C, S> {
+    type Target = C;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.guard.collection
+    }
+}
+
+/// Undo log entry of [`Vec`]
+pub enum VecChange<T> {
+    /// An element is pushed
+    Pushed,
+    /// An element is popped
+    Popped(T),
+    /// An element is inserted at the index
+    Inserted(usize),
+    /// An element is removed from the index
+    Removed(usize, T),
+    /// Two elements are swapped
+    Swapped(usize, usize),
+    /// Elements are truncated
+    Truncated(Vec<T>),
+    /// An element at the index is replaced
+    Replaced(usize, T),
+}
+
+impl<T> Kept for VecChange<T> {
+    type Element = T;
+
+    fn kept(&self) -> Option<&T> {
+        match self {
+            VecChange::Popped(value) | VecChange::Removed(_, value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Undo for Vec<T> {
+    type Entry = VecChange<T>;
+
+    fn undo(&mut self, entry: VecChange<T>) {
+        match entry {
+            VecChange::Pushed => {
+                self.pop();
+            }
+            VecChange::Popped(value) => self.push(value),
+            VecChange::Inserted(index) => {
+                self.remove(index);
+            }
+            VecChange::Removed(index, value) => self.insert(index, value),
+            VecChange::Swapped(a, b) => self.swap(a, b),
+            VecChange::Truncated(tail) => self.extend(tail),
+            VecChange::Replaced(index, value) => self[index] = value,
+        }
+    }
+}
+
+/// Transactional [`Vec`]
+pub type TxVec<'a, T, S = Outcome> = Tx<'a, Vec<T>, S>;
+
+impl<T, S: CommitState> TxVec<'_, T, S> {
+    /// Append `value` to the back.
+    pub fn push(&mut self, value: T) {
+        self.record(|vec| {
+            vec.push(value);
+            (VecChange::Pushed, ())
+        });
+    }
+
+    /// Remove the last element, and return a reference to it, which is kept
+    /// in the undo log.
+    pub fn pop(&mut self) -> Option<&T> {
+        let value = self.guard.collection.pop()?;
+        Some(self.guard.push_kept(VecChange::Popped(value)))
+    }
+
+    /// Insert `value` at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, value: T) {
+        self.record(|vec| {
+            vec.insert(index, value);
+            (VecChange::Inserted(index), ())
+        });
+    }
+
+    /// Remove the element at `index`, and return a reference to it, which is
+    /// kept in the undo log.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> &T {
+        let value = self.guard.collection.remove(index);
+        self.guard.push_kept(VecChange::Removed(index, value))
+    }
+
+    /// Swap two elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` is out of bounds.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.record(|vec| {
+            vec.swap(a, b);
+            (VecChange::Swapped(a, b), ())
+        });
+    }
+
+    /// Shorten the vector to `len` elements, keeping the removed ones in the
+    /// undo log. It has no effect if `len` is not less than current length.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.guard.collection.len() {
+            self.record(|vec| (VecChange::Truncated(vec.split_off(len)), ()));
+        }
+    }
+
+    /// Remove all elements, keeping them in the undo log.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Replace the element at `index` by `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn replace(&mut self, index: usize, value: T) {
+        self.record(|vec| {
+            let previous = core::mem::replace(&mut vec[index], value);
+            (VecChange::Replaced(index, previous), ())
+        });
+    }
+}
+
+/// Undo log entry of [`VecDeque`]
+pub enum VecDequeChange<T> {
+    /// An element is pushed to the back
+    PushedBack,
+    /// An element is pushed to the front
+    PushedFront,
+    /// An element is popped from the back
+    PoppedBack(T),
+    /// An element is popped from the front
+    PoppedFront(T),
+    /// An element is inserted at the index
+    Inserted(usize),
+    /// An element is removed from the index
+    Removed(usize, T),
+    /// Two elements are swapped
+    Swapped(usize, usize),
+    /// Elements are truncated
+    Truncated(VecDeque<T>),
+    /// An element at the index is replaced
+    Replaced(usize, T),
+}
+
+impl<T> Kept for VecDequeChange<T> {
+    type Element = T;
+
+    fn kept(&self) -> Option<&T> {
+        match self {
+            VecDequeChange::PoppedBack(value)
+            | VecDequeChange::PoppedFront(value)
+            | VecDequeChange::Removed(_, value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Undo for VecDeque<T> {
+    type Entry = VecDequeChange<T>;
+
+    fn undo(&mut self, entry: VecDequeChange<T>) {
+        match entry {
+            VecDequeChange::PushedBack => {
+                self.pop_back();
+            }
+            VecDequeChange::PushedFront => {
+                self.pop_front();
+            }
+            VecDequeChange::PoppedBack(value) => self.push_back(value),
+            VecDequeChange::PoppedFront(value) => self.push_front(value),
+            VecDequeChange::Inserted(index) => {
+                self.remove(index);
+            }
+            VecDequeChange::Removed(index, value) => self.insert(index, value),
+            VecDequeChange::Swapped(a, b) => self.swap(a, b),
+            VecDequeChange::Truncated(mut tail) => self.append(&mut tail),
+            VecDequeChange::Replaced(index, value) => self[index] = value,
+        }
+    }
+}
+
+/// Transactional [`VecDeque`]
+pub type TxVecDeque<'a, T, S = Outcome> = Tx<'a, VecDeque<T>, S>;
+
+impl<T, S: CommitState> TxVecDeque<'_, T, S> {
+    /// Append `value` to the back.
+    pub fn push_back(&mut self, value: T) {
+        self.record(|deque| {
+            deque.push_back(value);
+            (VecDequeChange::PushedBack, ())
+        });
+    }
+
+    /// Prepend `value` to the front.
+    pub fn push_front(&mut self, value: T) {
+        self.record(|deque| {
+            deque.push_front(value);
+            (VecDequeChange::PushedFront, ())
+        });
+    }
+
+    /// Remove the last element, and return a reference to it, which is kept
+    /// in the undo log.
+    pub fn pop_back(&mut self) -> Option<&T> {
+        let value = self.guard.collection.pop_back()?;
+        Some(self.guard.push_kept(VecDequeChange::PoppedBack(value)))
+    }
+
+    /// Remove the first element, and return a reference to it, which is kept
+    /// in the undo log.
+    pub fn pop_front(&mut self) -> Option<&T> {
+        let value = self.guard.collection.pop_front()?;
+        Some(self.guard.push_kept(VecDequeChange::PoppedFront(value)))
+    }
+
+    /// Insert `value` at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, value: T) {
+        self.record(|deque| {
+            deque.insert(index, value);
+            (VecDequeChange::Inserted(index), ())
+        });
+    }
+
+    /// Remove the element at `index`, and return a reference to it, which is
+    /// kept in the undo log.
+    pub fn remove(&mut self, index: usize) -> Option<&T> {
+        let value = self.guard.collection.remove(index)?;
+        Some(self.guard.push_kept(VecDequeChange::Removed(index, value)))
+    }
+
+    /// Swap two elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` is out of bounds.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.record(|deque| {
+            deque.swap(a, b);
+            (VecDequeChange::Swapped(a, b), ())
+        });
+    }
+
+    /// Shorten the deque to `len` elements, keeping the removed ones in the
+    /// undo log. It has no effect if `len` is not less than current length.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.guard.collection.len() {
+            self.record(|deque| (VecDequeChange::Truncated(deque.split_off(len)), ()));
+        }
+    }
+
+    /// Remove all elements, keeping them in the undo log.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Replace the element at `index` by `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn replace(&mut self, index: usize, value: T) {
+        self.record(|deque| {
+            let previous = core::mem::replace(&mut deque[index], value);
+            (VecDequeChange::Replaced(index, previous), ())
+        });
+    }
+}
+
+/// Undo log entry of maps, which restores the previous value of the key
+pub struct MapChange<K, V> {
+    key: K,
+    /// Previous value, where `None` means the key was absent
+    previous: Option<V>,
+}
+
+impl<K, V> Kept for MapChange<K, V> {
+    type Element = V;
+
+    fn kept(&self) -> Option<&V> {
+        self.previous.as_ref()
+    }
+}
+
+impl<K: Ord, V> Undo for BTreeMap<K, V> {
+    type Entry = MapChange<K, V>;
+
+    fn undo(&mut self, entry: MapChange<K, V>) {
+        match entry.previous {
+            Some(value) => {
+                self.insert(entry.key, value);
+            }
+            None => {
+                self.remove(&entry.key);
+            }
+        }
+    }
+}
+
+/// Transactional [`BTreeMap`]
+pub type TxBTreeMap<'a, K, V, S = Outcome> = Tx<'a, BTreeMap<K, V>, S>;
+
+impl<K: Ord + Clone, V, S: CommitState> TxBTreeMap<'_, K, V, S> {
+    /// Insert `value` at `key`, and return a reference to the previous value,
+    /// which is kept in the undo log.
+    pub fn insert(&mut self, key: K, value: V) -> Option<&V> {
+        let previous = self.guard.collection.insert(key.clone(), value);
+        self.guard.push(MapChange { key, previous }).kept()
+    }
+
+    /// Remove `key`, and return a reference to the removed value, which is kept
+    /// in the undo log.
+    pub fn remove(&mut self, key: &K) -> Option<&V> {
+        let (key, value) = self.guard.collection.remove_entry(key)?;
+        self.guard
+            .push(MapChange {
+                key,
+                previous: Some(value),
+            })
+            .kept()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, H> Undo for std::collections::HashMap<K, V, H>
+where
+    K: Eq + core::hash::Hash,
+    H: core::hash::BuildHasher,
+{
+    type Entry = MapChange<K, V>;
+
+    fn undo(&mut self, entry: MapChange<K, V>) {
+        match entry.previous {
+            Some(value) => {
+                self.insert(entry.key, value);
+            }
+            None => {
+                self.remove(&entry.key);
+            }
+        }
+    }
+}
+
+/// Transactional `HashMap`
+#[cfg(feature = "std")]
+pub type TxHashMap<'a, K, V, S = Outcome, H = std::collections::hash_map::RandomState> =
+    Tx<'a, std::collections::HashMap<K, V, H>, S>;
+
+#[cfg(feature = "std")]
+impl<K, V, S, H> TxHashMap<'_, K, V, S, H>
+where
+    K: Eq + core::hash::Hash + Clone,
+    S: CommitState,
+    H: core::hash::BuildHasher,
+{
+    /// Insert `value` at `key`, and return a reference to the previous value,
+    /// which is kept in the undo log.
+    pub fn insert(&mut self, key: K, value: V) -> Option<&V> {
+        let previous = self.guard.collection.insert(key.clone(), value);
+        self.guard.push(MapChange { key, previous }).kept()
+    }
+
+    /// Remove `key`, and return a reference to the removed value, which is kept
+    /// in the undo log.
+    pub fn remove(&mut self, key: &K) -> Option<&V> {
+        let (key, value) = self.guard.collection.remove_entry(key)?;
+        self.guard
+            .push(MapChange {
+                key,
+                previous: Some(value),
+            })
+            .kept()
+    }
+}
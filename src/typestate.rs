@@ -0,0 +1,128 @@
+//! Typestate stated scope guard.
+//!
+//! [`ScopeGuard::set_state`] accepts any state at any time, so nothing stops the
+//! code from jumping from `Created` straight to `Committed` without going through
+//! `Validated`. [`TypedScopeGuard`] takes the state as a type parameter instead.
+//! Each state type decides its own drop behavior by implementing [`TypeState`],
+//! and a transition consumes the guard and returns a new one typed with the new
+//! state, which is only allowed if declared by [`TransitionTo`].
+//!
+//! ```
+//! use stated_scope_guard::typestate::{TransitionTo, TypeState, TypedScopeGuard};
+//!
+//! # struct Account;
+//! # fn delete_account(_: Account) {}
+//! # fn validate(_: &Account) -> Result<(), ()> { Ok(()) }
+//! struct Created;
+//! struct Validated;
+//! struct Committed;
+//!
+//! impl TypeState<Account> for Created {
+//!     fn on_drop(account: Account, _: &Self) {
+//!         delete_account(account);
+//!     }
+//! }
+//! impl TypeState<Account> for Validated {
+//!     fn on_drop(account: Account, _: &Self) {
+//!         delete_account(account);
+//!     }
+//! }
+//! impl TypeState<Account> for Committed {
+//!     fn on_drop(_: Account, _: &Self) {}
+//! }
+//!
+//! impl TransitionTo<Validated> for Created {}
+//! impl TransitionTo<Committed> for Validated {}
+//!
+//! fn setup() -> Result<(), ()> {
+//!     let guard = TypedScopeGuard::new(Account, Created);
+//!     validate(&guard)?;
+//!     let guard = guard.transition(Validated);
+//!     let _guard = guard.transition(Committed);
+//!     Ok(())
+//! }
+//! # setup().unwrap();
+//! ```
+//!
+//! Undeclared transitions will not compile:
+//!
+//! ```compile_fail
+//! # use stated_scope_guard::typestate::{TransitionTo, TypeState, TypedScopeGuard};
+//! # struct Created;
+//! # struct Committed;
+//! # impl TypeState<()> for Created { fn on_drop(_: (), _: &Self) {} }
+//! # impl TypeState<()> for Committed { fn on_drop(_: (), _: &Self) {} }
+//! let guard = TypedScopeGuard::new((), Created);
+//! let guard = guard.transition(Committed);
+//! ```
+
+use core::ops::{Deref, DerefMut};
+
+use crate::ScopeGuard;
+
+/// State type of [`TypedScopeGuard`], which decides how the value is dealt
+/// with when the guard is dropped in this state
+pub trait TypeState<T>: Sized {
+    /// Deal with `value` when the guard is dropped in `state`.
+    fn on_drop(value: T, state: &Self);
+}
+
+/// Declare that a [`TypedScopeGuard`] in this state can transition to state `To`
+pub trait TransitionTo<To> {}
+
+/// Typestate stated scope guard
+pub struct TypedScopeGuard<T, S> {
+    guard: ScopeGuard<T, S, fn(T, &S)>,
+}
+
+impl<T, S> TypedScopeGuard<T, S>
+where
+    S: TypeState<T>,
+{
+    /// Create a new typestate stated scope guard in `state`.
+    pub fn new(value: T, state: S) -> Self {
+        Self {
+            guard: ScopeGuard::new(value, state, S::on_drop),
+        }
+    }
+
+    /// Transition to state `to`, which must be declared by [`TransitionTo`].
+    pub fn transition<To>(self, to: To) -> TypedScopeGuard<T, To>
+    where
+        S: TransitionTo<To>,
+        To: TypeState<T>,
+    {
+        TypedScopeGuard::new(self.guard.into_inner(), to)
+    }
+
+    /// Finish the scope guard now, dealing with the value according to current state.
+    pub fn finish(self) {
+        self.guard.finish()
+    }
+
+    /// Defuse the scope guard, and take the inner value out without dealing with it.
+    pub fn into_inner(self) -> T {
+        self.guard.into_inner()
+    }
+
+    /// Defuse the scope guard, and take the inner value and state out without
+    /// dealing with the value.
+    pub fn into_parts(self) -> (T, S) {
+        let (value, state, _) = self.guard.into_parts();
+        (value, state)
+    }
+}
+
+impl<T, S> Deref for TypedScopeGuard<T, S> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T, S> DerefMut for TypedScopeGuard<T, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
@@ -0,0 +1,169 @@
+//! Stated scope guard detecting a missing state decision.
+//!
+//! A common bug is a code path which succeeds, but forgets to set the state,
+//! so the guard silently rolls back a successful operation. In the undecided
+//! mode, the initial state of a guard is marked as not yet decided, and the
+//! state shall be set by [`decide`][ScopeGuard::decide]. When the guard is
+//! dropped on a normal exit without any decision, the callback is called with
+//! the initial state, and then an [`OnUndecided`] hook is triggered, such as
+//! [`DebugAssert`], [`Eprint`] or a closure. Since the callback is called
+//! first, the resource is still rolled back if the hook panics.
+//!
+//! Dropping because of a panic is not considered as a missing decision, and
+//! the hook is not triggered.
+//!
+//! ```should_panic
+//! use stated_scope_guard::undecided::{DebugAssert, UndecidedScopeGuard};
+//!
+//! #[derive(Debug, Clone, Copy, PartialEq)]
+//! enum State {
+//!     Success,
+//!     Failure,
+//! }
+//!
+//! fn operation(deleted: &mut bool) -> Result<(), ()> {
+//!     let _guard = UndecidedScopeGuard::undecided(
+//!         "log dir",
+//!         State::Failure,
+//!         |_, _| *deleted = true,
+//!         DebugAssert,
+//!     );
+//!     // Forgets `_guard.decide(State::Success)`
+//!     Ok(())
+//! }
+//!
+//! // Panics in debug builds, after the log dir is deleted
+//! let mut deleted = false;
+//! let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| operation(&mut deleted)));
+//! assert!(deleted);
+//! result.unwrap().unwrap();
+//! # if !cfg!(debug_assertions) { panic!() }
+//! ```
+
+use crate::unwind::DropReason;
+use crate::{ScopeGuard, StatedDrop};
+
+/// State of a guard in the undecided mode, which remembers whether it has been decided
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Undecided<S> {
+    state: S,
+    decided: bool,
+}
+
+impl<S> Undecided<S> {
+    /// Current state, which is the initial state if not decided
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Whether the state has been decided
+    pub fn is_decided(&self) -> bool {
+        self.decided
+    }
+}
+
+/// Hook triggered when a guard in the undecided mode is dropped on a normal
+/// exit without any decision
+///
+/// It is implemented for all closures taking the initial state as parameter.
+pub trait OnUndecided<S> {
+    /// Handle the missing decision, where `state` is the initial state.
+    fn on_undecided(self, state: &S);
+}
+
+impl<S, H> OnUndecided<S> for H
+where
+    H: FnOnce(&S),
+{
+    fn on_undecided(self, state: &S) {
+        self(state)
+    }
+}
+
+/// Hook failing a debug assertion, which does nothing in release builds
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DebugAssert;
+
+impl<S> OnUndecided<S> for DebugAssert {
+    fn on_undecided(self, _state: &S) {
+        debug_assert!(false, "scope guard is dropped without any state decision");
+    }
+}
+
+/// Hook printing a message with the initial state to the standard error
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Eprint;
+
+impl<S: core::fmt::Debug> OnUndecided<S> for Eprint {
+    fn on_undecided(self, state: &S) {
+        std::eprintln!(
+            "scope guard is dropped without any state decision, initial state: {state:?}"
+        );
+    }
+}
+
+/// Callback of a guard in the undecided mode, which calls the inner callback,
+/// and then triggers the hook if the state is not decided
+pub struct UndecidedCallback<F, H> {
+    callback: F,
+    hook: H,
+}
+
+impl<T, S, F, H> StatedDrop<T, Undecided<S>> for UndecidedCallback<F, H>
+where
+    F: StatedDrop<T, S>,
+    H: OnUndecided<S>,
+{
+    type Output = F::Output;
+
+    fn call(self, value: T, state: &Undecided<S>) -> F::Output {
+        let output = self.callback.call(value, &state.state);
+        if !state.decided && DropReason::current() == DropReason::Normal {
+            self.hook.on_undecided(&state.state);
+        }
+        output
+    }
+}
+
+/// Stated scope guard in the undecided mode
+pub type UndecidedScopeGuard<T, S, F, H> = ScopeGuard<T, Undecided<S>, UndecidedCallback<F, H>>;
+
+impl<T, S, F, H> UndecidedScopeGuard<T, S, F, H>
+where
+    F: StatedDrop<T, S>,
+    H: OnUndecided<S>,
+{
+    /// Create a new stated scope guard in the undecided mode, whose `callback`
+    /// is a closure or function. The `hook` is triggered if the guard is dropped
+    /// on a normal exit without [`decide`][ScopeGuard::decide].
+    pub fn undecided<R>(value: T, initial: S, callback: F, hook: H) -> Self
+    where
+        F: FnOnce(T, &S) -> R,
+    {
+        Self::undecided_with_handler(value, initial, callback, hook)
+    }
+
+    /// Create a new stated scope guard in the undecided mode, whose callback
+    /// is any implementor of [`StatedDrop`].
+    pub fn undecided_with_handler(value: T, initial: S, handler: F, hook: H) -> Self {
+        ScopeGuard::with_handler(
+            value,
+            Undecided {
+                state: initial,
+                decided: false,
+            },
+            UndecidedCallback {
+                callback: handler,
+                hook,
+            },
+        )
+    }
+
+    /// Decide current state to be `state`
+    pub fn decide(&mut self, state: S) {
+        self.set_state(Undecided {
+            state,
+            decided: true,
+        });
+    }
+}
@@ -0,0 +1,141 @@
+//! Panic-aware stated scope guard.
+//!
+//! [`ScopeGuard`] calls its callback with the last state set, no matter whether
+//! the scope ends normally or because of a panic. With the `std` feature enabled,
+//! the reason of dropping can be detected by [`std::thread::panicking`], and this
+//! module provides scope guards which take it into consideration:
+//!
+//! * [`new_with_reason`]: the [`DropReason`] is passed to the callback.
+//! * [`new_with_unwind_state`]: when unwinding, the callback is called with an
+//!   override state instead of the current state.
+//! * [`new_with_strategy`]: whether to call the callback is decided by a [`Strategy`],
+//!   such as [`OnSuccess`], [`OnUnwind`] and [`Always`].
+//!
+//! ```
+//! use std::cell::Cell;
+//! use std::panic::{catch_unwind, AssertUnwindSafe};
+//! use stated_scope_guard::unwind::new_with_unwind_state;
+//!
+//! #[derive(Debug, Clone, Copy, PartialEq)]
+//! enum State {
+//!     Committed,
+//!     RolledBack,
+//! }
+//!
+//! let dropped_with = Cell::new(None);
+//! let result = catch_unwind(AssertUnwindSafe(|| {
+//!     let mut guard = new_with_unwind_state((), State::RolledBack, State::RolledBack, |_, state| {
+//!         dropped_with.set(Some(*state));
+//!     });
+//!     guard.set_state(State::Committed);
+//!     panic!("something goes wrong after commit");
+//! }));
+//! assert!(result.is_err());
+//! assert_eq!(dropped_with.get(), Some(State::RolledBack));
+//! ```
+
+use crate::ScopeGuard;
+
+/// Reason of dropping a scope guard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// The scope ends normally, e.g., by reaching its end, `return` or `?`
+    Normal,
+    /// The scope ends because of a panic
+    Unwinding,
+}
+
+impl DropReason {
+    /// Detect the reason of dropping for current thread.
+    pub fn current() -> Self {
+        if std::thread::panicking() {
+            Self::Unwinding
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+/// Strategy to decide whether to call the callback according to [`DropReason`]
+pub trait Strategy {
+    /// Whether the callback should be called when dropped for `reason`.
+    fn should_run(&self, reason: DropReason) -> bool;
+}
+
+/// Always call the callback
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Always;
+
+/// Only call the callback when the scope ends normally
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OnSuccess;
+
+/// Only call the callback when the scope ends because of a panic
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OnUnwind;
+
+impl Strategy for Always {
+    fn should_run(&self, _reason: DropReason) -> bool {
+        true
+    }
+}
+
+impl Strategy for OnSuccess {
+    fn should_run(&self, reason: DropReason) -> bool {
+        reason == DropReason::Normal
+    }
+}
+
+impl Strategy for OnUnwind {
+    fn should_run(&self, reason: DropReason) -> bool {
+        reason == DropReason::Unwinding
+    }
+}
+
+/// Create a new stated scope guard, whose `callback` takes the [`DropReason`]
+/// as an additional parameter.
+pub fn new_with_reason<T, S, F: FnOnce(T, &S, DropReason)>(
+    value: T,
+    state: S,
+    callback: F,
+) -> ScopeGuard<T, S, impl FnOnce(T, &S)> {
+    ScopeGuard::new(value, state, |value, state| {
+        callback(value, state, DropReason::current())
+    })
+}
+
+/// Create a new stated scope guard, whose `callback` will be called with
+/// `unwind_state` instead of current state when dropped because of a panic.
+///
+/// As a result, a panic will always trigger the action of `unwind_state`, even if
+/// the state has already been set to something else, such as a committed state.
+pub fn new_with_unwind_state<T, S, F: FnOnce(T, &S)>(
+    value: T,
+    state: S,
+    unwind_state: S,
+    callback: F,
+) -> ScopeGuard<T, S, impl FnOnce(T, &S)> {
+    ScopeGuard::new(
+        value,
+        state,
+        move |value, state| match DropReason::current() {
+            DropReason::Normal => callback(value, state),
+            DropReason::Unwinding => callback(value, &unwind_state),
+        },
+    )
+}
+
+/// Create a new stated scope guard, whose `callback` will only be called when
+/// `strategy` allows for current [`DropReason`].
+pub fn new_with_strategy<T, S, St: Strategy, F: FnOnce(T, &S)>(
+    value: T,
+    state: S,
+    strategy: St,
+    callback: F,
+) -> ScopeGuard<T, S, impl FnOnce(T, &S)> {
+    ScopeGuard::new(value, state, move |value, state| {
+        if strategy.should_run(DropReason::current()) {
+            callback(value, state)
+        }
+    })
+}
@@ -0,0 +1,13 @@
+use std::fs;
+use std::path::PathBuf;
+
+/// Create an empty temporary directory for test `name` of the `suite`
+pub fn test_dir(suite: &str, name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "stated-scope-guard-{suite}-{name}-{}",
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
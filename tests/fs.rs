@@ -0,0 +1,113 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use stated_scope_guard::fs::{self as guarded_fs, FsTransaction};
+use stated_scope_guard::Outcome;
+
+fn test_dir(name: &str) -> PathBuf {
+    common::test_dir("fs", name)
+}
+
+/// Sorted names of the entries of `dir`, so that leftover backups are noticed
+fn entries(dir: &Path) -> Vec<String> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn rename_over_existing_target_is_reverted() {
+    let dir = test_dir("rename-revert");
+    fs::write(dir.join("new"), "new").unwrap();
+    fs::write(dir.join("config"), "old").unwrap();
+
+    let guard = guarded_fs::rename(dir.join("new"), dir.join("config")).unwrap();
+    assert!(!dir.join("new").exists());
+    assert_eq!(fs::read_to_string(dir.join("config")).unwrap(), "new");
+    guard.finish().unwrap();
+
+    assert_eq!(fs::read_to_string(dir.join("new")).unwrap(), "new");
+    assert_eq!(fs::read_to_string(dir.join("config")).unwrap(), "old");
+    assert_eq!(entries(&dir), ["config", "new"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rename_over_existing_target_is_committed() {
+    let dir = test_dir("rename-commit");
+    fs::write(dir.join("new"), "new").unwrap();
+    fs::write(dir.join("config"), "old").unwrap();
+
+    let mut guard = guarded_fs::rename(dir.join("new"), dir.join("config")).unwrap();
+    guard.set_state(Outcome::Commit);
+    guard.finish().unwrap();
+
+    assert_eq!(fs::read_to_string(dir.join("config")).unwrap(), "new");
+    assert_eq!(entries(&dir), ["config"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn remove_directory_is_reverted() {
+    let dir = test_dir("remove-revert");
+    fs::create_dir_all(dir.join("logs/app")).unwrap();
+    fs::write(dir.join("logs/app/log"), "entry").unwrap();
+
+    let guard = guarded_fs::remove(dir.join("logs")).unwrap();
+    assert!(!dir.join("logs").exists());
+    drop(guard);
+
+    assert_eq!(
+        fs::read_to_string(dir.join("logs/app/log")).unwrap(),
+        "entry"
+    );
+    assert_eq!(entries(&dir), ["logs"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn remove_directory_is_committed() {
+    let dir = test_dir("remove-commit");
+    fs::create_dir_all(dir.join("logs/app")).unwrap();
+    fs::write(dir.join("logs/app/log"), "entry").unwrap();
+
+    let mut guard = guarded_fs::remove(dir.join("logs")).unwrap();
+    guard.set_state(Outcome::Commit);
+    guard.finish().unwrap();
+
+    assert!(entries(&dir).is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn savepoint_reverts_only_later_operations() {
+    let dir = test_dir("savepoint");
+    fs::write(dir.join("config"), "old").unwrap();
+
+    let mut transaction = FsTransaction::new();
+    transaction.create_dir_all(dir.join("logs")).unwrap();
+    {
+        let mut savepoint = transaction.savepoint();
+        savepoint.write(dir.join("config"), "new").unwrap();
+        savepoint.create_dir_all(dir.join("cache")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("config")).unwrap(), "new");
+    }
+    assert_eq!(fs::read_to_string(dir.join("config")).unwrap(), "old");
+    assert_eq!(entries(&dir), ["config", "logs"]);
+    {
+        let mut savepoint = transaction.savepoint();
+        savepoint.remove(dir.join("config")).unwrap();
+        savepoint.release();
+    }
+    transaction.commit().unwrap();
+
+    assert_eq!(entries(&dir), ["logs"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
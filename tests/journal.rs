@@ -0,0 +1,208 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use stated_scope_guard::journal::{recover, JournaledTransaction, UndoRecord};
+
+/// Environment variable telling the test binary to run as the crashing child
+const CHILD_DIR: &str = "STATED_SCOPE_GUARD_JOURNAL_CHILD_DIR";
+
+struct RemoveFile(PathBuf);
+
+impl UndoRecord for RemoveFile {
+    fn encode(&self) -> Vec<u8> {
+        self.0.to_str().unwrap().as_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let path = std::str::from_utf8(bytes).map_err(io::Error::other)?;
+        Ok(Self(PathBuf::from(path)))
+    }
+
+    fn undo(self) -> io::Result<()> {
+        match fs::remove_file(&self.0) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn test_dir(name: &str) -> PathBuf {
+    common::test_dir("journal", name)
+}
+
+/// Record and create `count` files, without committing.
+fn record_files(dir: &Path, count: usize) -> JournaledTransaction<RemoveFile> {
+    let mut transaction = JournaledTransaction::create(dir.join("journal")).unwrap();
+    for index in 0..count {
+        let path = dir.join(format!("file-{index}"));
+        transaction.record(RemoveFile(path.clone())).unwrap();
+        fs::write(&path, "content").unwrap();
+    }
+    transaction
+}
+
+/// Run as the child process when spawned by [`killed_between_record_and_commit`],
+/// which records two files, reports it is ready, and waits to be killed.
+#[test]
+fn journal_child() {
+    let Some(dir) = std::env::var_os(CHILD_DIR) else {
+        return;
+    };
+    let _transaction = record_files(Path::new(&dir), 2);
+    println!("ready");
+    io::stdout().flush().unwrap();
+    loop {
+        std::thread::park();
+    }
+}
+
+#[test]
+fn killed_between_record_and_commit() {
+    let dir = test_dir("killed");
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args([
+            "--exact",
+            "journal_child",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(CHILD_DIR, &dir)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    assert!(stdout.lines().any(|line| line.unwrap().contains("ready")));
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert!(dir.join("file-0").exists());
+    assert!(dir.join("file-1").exists());
+    assert_eq!(recover::<RemoveFile>(dir.join("journal")).unwrap(), 2);
+    assert!(!dir.join("file-0").exists());
+    assert!(!dir.join("file-1").exists());
+    assert!(!dir.join("journal").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn partial_last_frame_is_ignored() {
+    let dir = test_dir("partial");
+    std::mem::forget(record_files(&dir, 2));
+    // The last frame is cut short by a crash while appending it
+    let journal = dir.join("journal");
+    let len = fs::metadata(&journal).unwrap().len();
+    OpenOptions::new()
+        .write(true)
+        .open(&journal)
+        .unwrap()
+        .set_len(len - 3)
+        .unwrap();
+
+    assert_eq!(recover::<RemoveFile>(&journal).unwrap(), 1);
+    assert!(!dir.join("file-0").exists());
+    assert!(!journal.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn partial_last_header_is_ignored() {
+    let dir = test_dir("partial-header");
+    std::mem::forget(record_files(&dir, 1));
+    let mut journal = OpenOptions::new()
+        .append(true)
+        .open(dir.join("journal"))
+        .unwrap();
+    journal.write_all(&[0, 100, 0]).unwrap();
+    drop(journal);
+
+    assert_eq!(recover::<RemoveFile>(dir.join("journal")).unwrap(), 1);
+    assert!(!dir.join("file-0").exists());
+    assert!(!dir.join("journal").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn torn_last_frame_is_ignored() {
+    let dir = test_dir("torn");
+    std::mem::forget(record_files(&dir, 2));
+    // The payload of the last frame is fully sized, but not fully written
+    let journal = dir.join("journal");
+    let mut bytes = fs::read(&journal).unwrap();
+    *bytes.last_mut().unwrap() ^= 0xFF;
+    fs::write(&journal, bytes).unwrap();
+
+    assert_eq!(recover::<RemoveFile>(&journal).unwrap(), 1);
+    assert!(!dir.join("file-0").exists());
+    // Its forward action was never performed, so it is not undone
+    assert!(dir.join("file-1").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn corrupted_frame_is_reported() {
+    let dir = test_dir("corrupted");
+    std::mem::forget(record_files(&dir, 2));
+    let journal = dir.join("journal");
+    let mut bytes = fs::read(&journal).unwrap();
+    // Corrupt the payload of the first frame, which is followed by another frame
+    bytes[14] ^= 0xFF;
+    fs::write(&journal, bytes).unwrap();
+
+    let error = recover::<RemoveFile>(&journal).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    // Nothing is undone, and the journal is kept
+    assert!(dir.join("file-0").exists());
+    assert!(dir.join("file-1").exists());
+    assert!(journal.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn corrupted_length_is_reported() {
+    let dir = test_dir("corrupted-length");
+    std::mem::forget(record_files(&dir, 3));
+    let journal = dir.join("journal");
+    let mut bytes = fs::read(&journal).unwrap();
+    // Corrupt the length of the first frame, so that its payload seems to run
+    // past the end of the journal
+    bytes[4] ^= 0x01;
+    fs::write(&journal, bytes).unwrap();
+
+    let error = recover::<RemoveFile>(&journal).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    for index in 0..3 {
+        assert!(dir.join(format!("file-{index}")).exists());
+    }
+    assert!(journal.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn committed_journal_is_not_undone() {
+    let dir = test_dir("committed");
+    let transaction = record_files(&dir, 1);
+    // Keep the journal file alive after commit removes it, which is what a crash
+    // between syncing the commit marker and removing the journal leaves behind
+    let journal = dir.join("journal-link");
+    fs::hard_link(dir.join("journal"), &journal).unwrap();
+    transaction.commit().unwrap();
+    assert!(!dir.join("journal").exists());
+
+    assert_eq!(recover::<RemoveFile>(&journal).unwrap(), 0);
+    assert!(dir.join("file-0").exists());
+    assert!(!journal.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_journal_is_nothing_to_recover() {
+    let dir = test_dir("missing");
+    assert_eq!(recover::<RemoveFile>(dir.join("journal")).unwrap(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
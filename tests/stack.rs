@@ -0,0 +1,63 @@
+#![cfg(feature = "std")]
+
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+
+use stated_scope_guard::savepoint::UndoLog;
+use stated_scope_guard::stack::GuardStack;
+
+/// Push callbacks recording their names, where the one named `panicking` panics
+fn push_all<'a>(
+    stack: &mut GuardStack<'a, bool>,
+    names: &[&'static str],
+    panicking: &'static str,
+    called: &'a RefCell<Vec<&'static str>>,
+) {
+    for &name in names {
+        stack.push(name, move |name, _| {
+            if name == panicking {
+                panic!("cannot delete {name}");
+            }
+            called.borrow_mut().push(name);
+        });
+    }
+}
+
+#[test]
+fn drop_calls_remaining_callbacks_after_panic() {
+    let called = RefCell::new(Vec::new());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut stack = GuardStack::new(false);
+        push_all(&mut stack, &["a", "b", "c"], "b", &called);
+    }));
+    assert!(result.is_err());
+    assert_eq!(called.into_inner(), ["c", "a"]);
+}
+
+#[test]
+fn finish_calls_remaining_callbacks_after_panic() {
+    let called = RefCell::new(Vec::new());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut stack = GuardStack::new(false);
+        push_all(&mut stack, &["a", "b", "c"], "b", &called);
+        stack.finish()
+    }));
+    assert!(result.is_err());
+    assert_eq!(called.into_inner(), ["c", "a"]);
+}
+
+#[test]
+fn rollback_to_calls_remaining_callbacks_after_panic() {
+    let called = RefCell::new(Vec::new());
+    let mut stack = GuardStack::new(false);
+    push_all(&mut stack, &["a"], "", &called);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        push_all(&mut stack, &["b", "c", "d"], "c", &called);
+        stack.rollback_to((1, false));
+    }));
+    assert!(result.is_err());
+    assert_eq!(*called.borrow(), ["d", "b"]);
+    assert_eq!(stack.len(), 1);
+    drop(stack);
+    assert_eq!(called.into_inner(), ["d", "b", "a"]);
+}
@@ -0,0 +1,174 @@
+#![cfg(feature = "std")]
+
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+
+use stated_scope_guard::two_phase::{Participant, ParticipantOutcome, TwoPhaseCommit};
+
+/// Phase at which a [`Store`] fails
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Fail {
+    Never,
+    Prepare,
+    PreparePanic,
+    Commit,
+    Abort,
+}
+
+/// In-memory participant, which records every phase called on it
+struct Store<'j> {
+    name: &'static str,
+    fail: Fail,
+    journal: &'j RefCell<Vec<String>>,
+}
+
+impl<'j> Store<'j> {
+    fn new(name: &'static str, fail: Fail, journal: &'j RefCell<Vec<String>>) -> Self {
+        Self {
+            name,
+            fail,
+            journal,
+        }
+    }
+
+    fn phase(&self, phase: &str, fail: Fail) -> Result<(), String> {
+        self.journal
+            .borrow_mut()
+            .push(format!("{phase} {}", self.name));
+        if self.fail == fail {
+            Err(format!("cannot {phase} {}", self.name))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Participant for Store<'_> {
+    type Error = String;
+
+    fn prepare(&mut self) -> Result<(), String> {
+        if self.fail == Fail::PreparePanic {
+            panic!("prepare {} panicked", self.name);
+        }
+        self.phase("prepare", Fail::Prepare)
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        self.phase("commit", Fail::Commit)
+    }
+
+    fn abort(&mut self) -> Result<(), String> {
+        self.phase("abort", Fail::Abort)
+    }
+}
+
+#[test]
+fn commit_failed_is_reported() {
+    let journal = RefCell::new(Vec::new());
+    let report = TwoPhaseCommit::new()
+        .participant("cache", Store::new("cache", Fail::Commit, &journal))
+        .participant("database", Store::new("database", Fail::Never, &journal))
+        .run();
+    assert!(report.is_committed());
+    assert!(!report.is_success());
+    assert_eq!(
+        report.outcomes(),
+        [
+            (
+                "cache",
+                ParticipantOutcome::CommitFailed(String::from("cannot commit cache"))
+            ),
+            ("database", ParticipantOutcome::Committed),
+        ]
+    );
+    assert_eq!(
+        journal.into_inner(),
+        [
+            "prepare cache",
+            "prepare database",
+            "commit cache",
+            "commit database"
+        ]
+    );
+}
+
+#[test]
+fn abort_failed_is_reported() {
+    let journal = RefCell::new(Vec::new());
+    let report = TwoPhaseCommit::new()
+        .participant("files", Store::new("files", Fail::Never, &journal))
+        .participant("cache", Store::new("cache", Fail::Abort, &journal))
+        .participant("database", Store::new("database", Fail::Prepare, &journal))
+        .run();
+    assert!(!report.is_committed());
+    assert_eq!(
+        report.outcomes(),
+        [
+            ("files", ParticipantOutcome::Aborted),
+            (
+                "cache",
+                ParticipantOutcome::AbortFailed(String::from("cannot abort cache"))
+            ),
+            (
+                "database",
+                ParticipantOutcome::PrepareFailed(String::from("cannot prepare database"))
+            ),
+        ]
+    );
+    assert_eq!(
+        journal.into_inner(),
+        [
+            "prepare files",
+            "prepare cache",
+            "prepare database",
+            "abort cache",
+            "abort files"
+        ]
+    );
+}
+
+#[test]
+fn dropping_prepared_aborts() {
+    let journal = RefCell::new(Vec::new());
+    let prepared = TwoPhaseCommit::new()
+        .participant("cache", Store::new("cache", Fail::Never, &journal))
+        .participant("database", Store::new("database", Fail::Never, &journal))
+        .prepare()
+        .unwrap_or_else(|_| panic!("all participants shall be prepared"));
+    drop(prepared);
+    assert_eq!(
+        journal.into_inner(),
+        [
+            "prepare cache",
+            "prepare database",
+            "abort database",
+            "abort cache"
+        ]
+    );
+}
+
+#[test]
+fn panic_in_prepare_aborts() {
+    let journal = RefCell::new(Vec::new());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        TwoPhaseCommit::new()
+            .participant("files", Store::new("files", Fail::Never, &journal))
+            .participant("cache", Store::new("cache", Fail::Never, &journal))
+            .participant(
+                "database",
+                Store::new("database", Fail::PreparePanic, &journal),
+            )
+            .participant("queue", Store::new("queue", Fail::Never, &journal))
+            .run()
+    }));
+    assert!(result.is_err());
+    assert_eq!(
+        journal.into_inner(),
+        [
+            "prepare files",
+            "prepare cache",
+            "abort cache",
+            "abort files"
+        ]
+    );
+}